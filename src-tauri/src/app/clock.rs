@@ -0,0 +1,29 @@
+use chrono::{ DateTime, TimeDelta, Utc };
+
+/// Source of the current time, so token expiry can be tested without waiting
+pub trait Clock {
+  fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> DateTime<Utc> {
+    Utc::now()
+  }
+}
+
+/// Tokens count as expired `skew` before their expiration date, in case the local clock runs behind the server's
+pub fn is_expired(clock: &impl Clock, expiration_date: DateTime<Utc>, skew: TimeDelta) -> bool {
+  clock.now() + skew >= expiration_date
+}
+
+#[cfg(test)]
+pub(crate) struct FixedClock(pub DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+  fn now(&self) -> DateTime<Utc> {
+    self.0
+  }
+}
@@ -1,12 +1,16 @@
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::launch::crash_report::CrashSummary;
+
 pub type StdError = Box<dyn std::error::Error>;
 
 #[derive(Debug, Error)]
 pub enum LauncherError {
   #[error(transparent)] Reqwest(#[from] reqwest::Error),
   #[error(transparent)] Io(#[from] std::io::Error),
+  #[error("Launch cancelled")] Cancelled,
+  #[error("The game crashed: {}", .0.headline())] GameCrashed(Box<CrashSummary>),
   #[error("{0}")] Other(String),
 }
 
@@ -16,6 +20,12 @@ impl From<StdError> for LauncherError {
   }
 }
 
+impl LauncherError {
+  pub fn is_cancelled(error: &StdError) -> bool {
+    matches!(error.downcast_ref::<LauncherError>(), Some(LauncherError::Cancelled))
+  }
+}
+
 impl Serialize for LauncherError {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
     serializer.serialize_str(&self.to_string())
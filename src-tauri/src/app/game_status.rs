@@ -2,6 +2,7 @@ use std::sync::Mutex;
 
 use serde::Serialize;
 use tauri::{ Emitter, WebviewWindow };
+use tokio_util::sync::CancellationToken;
 
 #[derive(Serialize, Clone, Default)]
 pub enum GameStatus {
@@ -14,6 +15,7 @@ pub enum GameStatus {
 pub struct GameStatusState {
   status: Mutex<GameStatus>,
   window: Mutex<Option<WebviewWindow>>,
+  cancel_token: Mutex<Option<CancellationToken>>,
 }
 
 impl GameStatusState {
@@ -26,7 +28,14 @@ impl GameStatusState {
   }
 
   pub fn set(&self, status: GameStatus) {
+    if matches!(status, GameStatus::Idle) {
+      self.cancel_token.lock().unwrap().take();
+    }
     *self.status.lock().unwrap() = status.clone();
+    self.emit(status);
+  }
+
+  fn emit(&self, status: GameStatus) {
     if let Some(window) = &*self.window.lock().unwrap() {
       let _ = window.emit("game_status", status);
     }
@@ -35,4 +44,31 @@ impl GameStatusState {
   pub fn get(&self) -> GameStatus {
     self.status.lock().unwrap().clone()
   }
+
+  /// Starts tracking a new launch, returning the token that cancels it.
+  /// Returns None if the launcher isn't idle, so a running launch or game keeps its token and status.
+  pub fn begin_launch(&self) -> Option<CancellationToken> {
+    let mut status = self.status.lock().unwrap();
+    if !matches!(*status, GameStatus::Idle) {
+      return None;
+    }
+    *status = GameStatus::Downloading;
+    drop(status);
+
+    let token = CancellationToken::new();
+    self.cancel_token.lock().unwrap().replace(token.clone());
+    self.emit(GameStatus::Downloading);
+    Some(token)
+  }
+
+  /// Cancels the current launch (or kills the game if it's already running). Returns false if there's nothing to cancel.
+  pub fn cancel(&self) -> bool {
+    match &*self.cancel_token.lock().unwrap() {
+      Some(token) => {
+        token.cancel();
+        true
+      }
+      None => false,
+    }
+  }
 }
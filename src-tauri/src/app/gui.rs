@@ -1,19 +1,39 @@
-use std::collections::HashMap;
+use std::{ collections::HashMap, fs, path::{ Path, PathBuf }, process::Command, time::Duration };
 
 use log::error;
-use minecraft_launcher_core::bootstrap::auth::UserAuthentication;
+use serde::Serialize;
 use sysinfo::System;
-use tauri::{ Builder, Manager, State, Url, WebviewWindow };
+use tauri::{ AppHandle, Builder, Emitter, Manager, State, Url, WebviewWindow };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-  config::{ auth::{ Authentication, MsaMojangAuth }, LauncherConfig },
-  constants::{ LAUNCHER_NAME, LAUNCHER_VERSION, G1GC_JRE_FLAGS, ZGC_JRE_FLAGS },
-  log_flusher::{ self, flush_all_logs },
+  config::{
+    accounts::RefreshTarget,
+    auth::{ Authentication, MsaMojangAuth },
+    mojang_api_helper::{ validate_skin, PlayerProfile, SkinType },
+    offline::{ offline_login_allowed, OfflineUuid },
+    LauncherConfig,
+  },
+  constants::{ create_launcher_client, LAUNCHER_DIRECTORY, LAUNCHER_NAME, LAUNCHER_VERSION, G1GC_JRE_FLAGS, MINECRAFT_SERVICES_URL, ZGC_JRE_FLAGS },
+  diagnostics::{ support_bundle::{ create_support_bundle, BundleInfo }, Diagnosis, RuleSet },
+  launch::{
+    crash_report::CrashSummary,
+    jvm_args::parse_jre_flags,
+    memory::{ count_installed_mods, SystemMemory },
+    preview::{ LaunchPreview, ScriptFormat },
+    session_log::{ list_sessions, read_session, sessions_dir, SessionInfo },
+  },
+  log_flusher::{ self, flush_all_logs, LogFlusher, GAME_LOGS },
+  log_query::{ LogPage, LogQuery },
+  log_record::{ parse_records, LogFormat },
+  logger::apply_logging_config,
   modpack_downloader::ModpackInfo,
 };
 
 use super::{ error::LauncherError, game, game_status::GameStatus, msa_auth, state::LauncherState };
 
+const SESSION_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
 #[tauri::command]
 async fn fetch_modpack_info(state: State<'_, LauncherState>) -> Result<ModpackInfo, LauncherError> {
   let mut downloader = state.modpack_downloader.lock().await;
@@ -26,6 +46,24 @@ fn get_system_memory() -> u64 {
   System::new_all().total_memory()
 }
 
+#[derive(Serialize)]
+struct MemoryInfo {
+  #[serde(flatten)]
+  system: SystemMemory,
+  max_allocatable_mb: u32,
+  recommended_mb: u32,
+}
+
+#[tauri::command]
+fn get_memory_info() -> MemoryInfo {
+  let system = SystemMemory::current();
+  MemoryInfo {
+    system,
+    max_allocatable_mb: system.max_allocatable_mb(),
+    recommended_mb: system.recommended_mb(count_installed_mods(&LAUNCHER_DIRECTORY)),
+  }
+}
+
 #[tauri::command]
 fn get_default_jre_flags() -> HashMap<String, String> {
   let mut flags = HashMap::new();
@@ -34,9 +72,15 @@ fn get_default_jre_flags() -> HashMap<String, String> {
   flags
 }
 
+#[tauri::command]
+fn validate_jre_flags(flags: &str) -> Result<Vec<String>, LauncherError> {
+  parse_jre_flags(flags).map_err(|err| LauncherError::Other(err.to_string()))
+}
+
 #[tauri::command]
 async fn start_game(state: State<'_, LauncherState>, window: WebviewWindow) -> Result<(), LauncherError> where WebviewWindow: Sync {
-  let res = game::launch_game(&state, &window).await.map_err(|e| e.into());
+  let cancel_token = begin_launch(&state)?;
+  let res = game::launch_game(&state, &window, cancel_token).await.map_err(|e| e.into());
   flush_all_logs(window.app_handle());
   if let Err(err) = &res {
     error!("Failed to start game: {}", err);
@@ -45,6 +89,133 @@ async fn start_game(state: State<'_, LauncherState>, window: WebviewWindow) -> R
   res
 }
 
+#[tauri::command]
+async fn dry_run_launch(state: State<'_, LauncherState>, window: WebviewWindow) -> Result<LaunchPreview, LauncherError> where WebviewWindow: Sync {
+  let cancel_token = begin_launch(&state)?;
+  let res = game::dry_run_launch(&state, &window, cancel_token).await.map_err(|e| e.into());
+  flush_all_logs(window.app_handle());
+  if let Err(err) = &res {
+    error!("Failed to prepare the launch: {}", err);
+  }
+  state.game_status.set(GameStatus::Idle);
+  res
+}
+
+/// Claims the game status for a new launch, so only the command that started it resets it back to idle
+fn begin_launch(state: &LauncherState) -> Result<CancellationToken, LauncherError> {
+  state.game_status.begin_launch().ok_or_else(|| LauncherError::Other("A launch is already in progress".to_string()))
+}
+
+#[tauri::command]
+fn export_launch_script(preview: LaunchPreview, format: Option<ScriptFormat>) -> Result<PathBuf, LauncherError> {
+  let format = format.unwrap_or_else(ScriptFormat::native);
+  let path = LAUNCHER_DIRECTORY.join(format!("launch.{}", format.extension()));
+  fs::write(&path, preview.to_script(format))?;
+  Ok(path)
+}
+
+#[tauri::command]
+fn cancel_launch(state: State<'_, LauncherState>) -> Result<(), LauncherError> {
+  if !matches!(state.game_status.get(), GameStatus::Downloading) || !state.game_status.cancel() {
+    return Err(LauncherError::Other("There is no launch in progress".to_string()));
+  }
+  Ok(())
+}
+
+#[tauri::command]
+fn kill_game(state: State<'_, LauncherState>) -> Result<(), LauncherError> {
+  if !matches!(state.game_status.get(), GameStatus::Playing) || !state.game_status.cancel() {
+    return Err(LauncherError::Other("The game is not running".to_string()));
+  }
+  Ok(())
+}
+
+#[tauri::command]
+fn get_last_crash(state: State<'_, LauncherState>) -> Option<CrashSummary> {
+  state.last_crash.lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn open_crash_report(state: State<'_, LauncherState>) -> Result<(), LauncherError> {
+  let report_path = state.last_crash.lock().unwrap().as_ref().and_then(|crash| crash.report_path.clone());
+  open_path(&report_path.ok_or_else(|| LauncherError::Other("No crash report available".to_string()))?)
+}
+
+#[tauri::command]
+fn list_game_sessions() -> Result<Vec<SessionInfo>, LauncherError> {
+  Ok(list_sessions(&sessions_dir(&LAUNCHER_DIRECTORY))?)
+}
+
+#[tauri::command]
+fn read_game_session(name: &str) -> Result<String, LauncherError> {
+  Ok(read_session(&sessions_dir(&LAUNCHER_DIRECTORY), name)?)
+}
+
+/// Searches the live logs (`game_logs`, `launcher_logs`) or a previous game session by its name
+#[tauri::command]
+fn query_logs(source: &str, query: LogQuery) -> Result<LogPage, LauncherError> {
+  let invalid_pattern = |err: regex::Error| LauncherError::Other(format!("Invalid search pattern: {err}"));
+  if let Some(flusher) = LogFlusher::find(source) {
+    return flusher.query(&query).map_err(invalid_pattern);
+  }
+  let text = read_session(&sessions_dir(&LAUNCHER_DIRECTORY), source)?;
+  query.run(&parse_records(LogFormat::Game, &text)).map_err(invalid_pattern)
+}
+
+/// Looks for known issues in the game logs and the last crash report
+#[tauri::command]
+fn analyze_game_logs(state: State<'_, LauncherState>) -> Vec<Diagnosis> {
+  let report_path = state.last_crash.lock().unwrap().as_ref().and_then(|crash| crash.report_path.clone());
+  let report = report_path.and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+  let logs = GAME_LOGS.get_text();
+  RuleSet::load(&LAUNCHER_DIRECTORY).analyze(&[("crash_report", &report), ("game_logs", &logs)])
+}
+
+/// Copies the reports of the last crash to the downloads folder, returning the main report's new path
+#[tauri::command]
+fn export_crash_report(state: State<'_, LauncherState>, app: AppHandle) -> Result<PathBuf, LauncherError> {
+  let crash = state.last_crash.lock().unwrap().clone().ok_or_else(|| LauncherError::Other("No crash report available".to_string()))?;
+  let report_path = crash.report_path.ok_or_else(|| LauncherError::Other("No crash report available".to_string()))?;
+  let target_dir = app.path().download_dir().map_err(|err| LauncherError::Other(format!("Failed to find the downloads folder: {err}")))?;
+
+  let copy = |path: &PathBuf| -> Result<PathBuf, LauncherError> {
+    let target = target_dir.join(path.file_name().unwrap_or_default());
+    fs::copy(path, &target)?;
+    Ok(target)
+  };
+  for path in &crash.other_reports {
+    copy(path)?;
+  }
+  copy(&report_path)
+}
+
+/// Zips the logs, crash reports and system details into the downloads folder, returning the zip path
+#[tauri::command]
+async fn export_support_bundle(state: State<'_, LauncherState>, app: AppHandle) -> Result<PathBuf, LauncherError> {
+  let target_dir = app.path().download_dir().map_err(|err| LauncherError::Other(format!("Failed to find the downloads folder: {err}")))?;
+  let config = state.launcher_config.lock().await.clone();
+  let modpack_info = state.modpack_downloader.lock().await.get_or_fetch_modpack_info().await.ok().cloned();
+
+  let info = BundleInfo { config, modpack_info };
+  tokio::task
+    ::spawn_blocking(move || create_support_bundle(&LAUNCHER_DIRECTORY, &target_dir, info).map_err(|err| LauncherError::Other(err.to_string())))
+    .await
+    .map_err(|err| LauncherError::Other(err.to_string()))?
+}
+
+/// Opens a file with the default program of the system
+fn open_path(path: &Path) -> Result<(), LauncherError> {
+  let program = if cfg!(windows) {
+    "explorer"
+  } else if cfg!(target_os = "macos") {
+    "open"
+  } else {
+    "xdg-open"
+  };
+  Command::new(program).arg(path).spawn()?;
+  Ok(())
+}
+
 #[tauri::command]
 fn get_game_status(state: State<'_, LauncherState>) -> GameStatus {
   state.game_status.get()
@@ -58,35 +229,202 @@ async fn get_launcher_config(state: State<'_, LauncherState>) -> Result<Launcher
 #[tauri::command]
 async fn set_launcher_config(state: State<'_, LauncherState>, config: LauncherConfig) -> Result<(), LauncherError> {
   let mut state = state.launcher_config.lock().await;
-  *state = config;
+  if state.logging != config.logging {
+    apply_logging_config(&config.logging).map_err(|err| LauncherError::Other(format!("Failed to apply logging config: {err}")))?;
+  }
+  // Accounts are only changed through their own commands
+  let LauncherConfig { accounts, active_account, .. } = state.clone();
+  *state = LauncherConfig { accounts, active_account, ..config };
   state.save_to_file()?;
   Ok(())
 }
 
+/// Applies `change` to the config, then saves it and sends it to the frontend
+async fn update_config<T>(
+  state: &LauncherState,
+  window: &WebviewWindow,
+  change: impl FnOnce(&mut LauncherConfig) -> Result<T, LauncherError>
+) -> Result<T, LauncherError> {
+  let mut config = state.launcher_config.lock().await;
+  let result = change(&mut config)?;
+  config.broadcast_update(window)?;
+  config.save_to_file()?;
+  Ok(result)
+}
+
 #[tauri::command]
-async fn login_offline(state: State<'_, LauncherState>, window: WebviewWindow, username: String) -> Result<(), LauncherError> {
-  let mut state = state.launcher_config.lock().await;
-  let auth = UserAuthentication::offline(&username);
-  state.authentication.replace(Authentication::Offline { username, uuid: auth.uuid });
-  state.broadcast_update(&window)?;
-  state.save_to_file()?;
-  Ok(())
+async fn login_offline(
+  state: State<'_, LauncherState>,
+  window: WebviewWindow,
+  username: String,
+  uuid: Option<OfflineUuid>
+) -> Result<(), LauncherError> {
+  let authentication = Authentication::offline(username, &uuid.unwrap_or_default()).map_err(|err| LauncherError::Other(err.to_string()))?;
+  update_config(&state, &window, |config| {
+    config.add_account(authentication);
+    Ok(())
+  }).await
+}
+
+#[tauri::command]
+fn is_offline_login_allowed() -> bool {
+  offline_login_allowed()
 }
 
 #[tauri::command]
 async fn login_msa(state: State<'_, LauncherState>, window: WebviewWindow) -> Result<(), LauncherError> {
+  let timeout = Duration::from_secs(state.launcher_config.lock().await.msa_login_timeout);
   let ms_auth_token = msa_auth
-    ::show_microsoft_prompt(&window).await
+    ::show_microsoft_prompt(&window, timeout).await
     .map_err(|err| LauncherError::Other(format!("Failed to get msa token: {}", err)))?;
-  let auth = MsaMojangAuth::from(ms_auth_token).await.map_err(|err| LauncherError::Other(format!("Failed to login: {}", err)))?;
+  let auth = MsaMojangAuth::from(&create_launcher_client(None), ms_auth_token).await.map_err(|err| LauncherError::Other(format!("Failed to login: {}", err)))?;
 
-  let mut state = state.launcher_config.lock().await;
-  state.authentication.replace(Authentication::Msa(auth));
-  state.broadcast_update(&window)?;
-  state.save_to_file()?;
+  update_config(&state, &window, |config| {
+    config.add_account(Authentication::Msa(auth));
+    Ok(())
+  }).await
+}
+
+#[tauri::command]
+async fn login_msa_device(state: State<'_, LauncherState>, window: WebviewWindow) -> Result<(), LauncherError> {
+  let cancel_token = CancellationToken::new();
+  if let Some(previous) = state.msa_device_login.lock().unwrap().replace(cancel_token.clone()) {
+    previous.cancel();
+  }
+  let result = msa_auth::device_code_login(&cancel_token, |code| {
+    let _ = window.emit("msa_device_code", code);
+  }).await.map_err(|err| LauncherError::Other(format!("Failed to get msa token: {}", err)));
+  // A cancelled token was already replaced or taken by `cancel_msa_device_login`
+  if !cancel_token.is_cancelled() {
+    state.msa_device_login.lock().unwrap().take();
+  }
+
+  let auth = MsaMojangAuth::from(&create_launcher_client(None), result?).await.map_err(|err| LauncherError::Other(format!("Failed to login: {}", err)))?;
+  update_config(&state, &window, |config| {
+    config.add_account(Authentication::Msa(auth));
+    Ok(())
+  }).await
+}
+
+#[tauri::command]
+fn cancel_msa_device_login(state: State<'_, LauncherState>) {
+  if let Some(cancel_token) = state.msa_device_login.lock().unwrap().take() {
+    cancel_token.cancel();
+  }
+}
+
+#[tauri::command]
+async fn switch_account(state: State<'_, LauncherState>, window: WebviewWindow, id: String) -> Result<(), LauncherError> {
+  update_config(&state, &window, |config| config.switch_account(&id)).await
+}
+
+#[tauri::command]
+async fn rename_account(state: State<'_, LauncherState>, window: WebviewWindow, id: String, label: Option<String>) -> Result<(), LauncherError> {
+  update_config(&state, &window, |config| config.rename_account(&id, label)).await
+}
+
+#[tauri::command]
+async fn remove_account(state: State<'_, LauncherState>, window: WebviewWindow, id: String) -> Result<(), LauncherError> {
+  update_config(&state, &window, |config| config.remove_account(&id).map(|_| ())).await
+}
+
+/// Removes the active account, which deletes its stored tokens, and clears the cookies of the login window.
+/// Microsoft has no endpoint to revoke the tokens of consumer accounts, wiping them is all that can be done.
+#[tauri::command]
+async fn logout(state: State<'_, LauncherState>, window: WebviewWindow) -> Result<(), LauncherError> {
+  msa_auth::clear_login_window(window.app_handle());
+  update_config(&state, &window, |config| {
+    let id = config.active_account.clone().ok_or_else(|| LauncherError::Other("Not logged in".to_string()))?;
+    config.remove_account(&id).map(|_| ())
+  }).await?;
+  msa_auth::clear_webview_data();
   Ok(())
 }
 
+#[tauri::command]
+async fn refresh_account(state: State<'_, LauncherState>, window: WebviewWindow, id: String) -> Result<(), LauncherError> {
+  let changed = LauncherConfig::refresh_sessions(&state.launcher_config, &create_launcher_client(None), RefreshTarget::Account(&id)).await?
+    .into_result()
+    .map_err(|err| LauncherError::Other(format!("Failed to refresh account: {}", err)))?;
+  if changed {
+    let config = state.launcher_config.lock().await;
+    config.broadcast_update(&window)?;
+    config.save_to_file()?;
+  }
+  Ok(())
+}
+
+/// Minecraft token of the active account, refreshed first if it's about to expire
+async fn active_minecraft_token(state: &LauncherState) -> Result<String, LauncherError> {
+  let changed = LauncherConfig::refresh_sessions(&state.launcher_config, &create_launcher_client(None), RefreshTarget::ActiveIfExpiring).await?
+    .into_result()
+    .map_err(|err| LauncherError::Other(format!("Failed to refresh session: {}", err)))?;
+  let config = state.launcher_config.lock().await;
+  if changed {
+    config.save_to_file()?;
+  }
+  match config.active_authentication() {
+    Some(Authentication::Msa(msa)) => Ok(msa.moj_token().to_string()),
+    _ => Err(LauncherError::Other("Skins and capes can only be changed with a Microsoft account".to_string())),
+  }
+}
+
+#[tauri::command]
+async fn get_player_profile(state: State<'_, LauncherState>) -> Result<PlayerProfile, LauncherError> {
+  let token = active_minecraft_token(&state).await?;
+  PlayerProfile::get(&create_launcher_client(None), MINECRAFT_SERVICES_URL, &token).await.map_err(|err| LauncherError::Other(err.to_string()))
+}
+
+#[tauri::command]
+async fn upload_skin(state: State<'_, LauncherState>, png: Vec<u8>, variant: SkinType) -> Result<PlayerProfile, LauncherError> {
+  validate_skin(&png).map_err(LauncherError::Other)?;
+  let token = active_minecraft_token(&state).await?;
+  Ok(PlayerProfile::upload_skin(&create_launcher_client(None), MINECRAFT_SERVICES_URL, &token, variant, png).await?)
+}
+
+#[tauri::command]
+async fn reset_skin(state: State<'_, LauncherState>) -> Result<PlayerProfile, LauncherError> {
+  let token = active_minecraft_token(&state).await?;
+  Ok(PlayerProfile::reset_skin(&create_launcher_client(None), MINECRAFT_SERVICES_URL, &token).await?)
+}
+
+#[tauri::command]
+async fn set_cape(state: State<'_, LauncherState>, cape_id: Option<String>) -> Result<PlayerProfile, LauncherError> {
+  let token = active_minecraft_token(&state).await?;
+  Ok(PlayerProfile::set_cape(&create_launcher_client(None), MINECRAFT_SERVICES_URL, &token, cape_id.as_deref()).await?)
+}
+
+/// Keeps the microsoft sessions fresh while the launcher stays open
+async fn refresh_sessions_periodically(app: AppHandle) {
+  let mut interval = tokio::time::interval(SESSION_REFRESH_INTERVAL);
+  // The sessions were just validated while loading the config
+  interval.tick().await;
+  loop {
+    interval.tick().await;
+    let state = app.state::<LauncherState>();
+    let refreshed = match LauncherConfig::refresh_sessions(&state.launcher_config, &create_launcher_client(None), RefreshTarget::Expiring).await {
+      Ok(refreshed) => refreshed,
+      Err(err) => {
+        error!("Failed to refresh sessions: {}", err);
+        continue;
+      }
+    };
+    for (username, err) in &refreshed.failed {
+      error!("Failed to refresh session of {}: {}", username, err);
+    }
+    if !refreshed.changed {
+      continue;
+    }
+    let config = state.launcher_config.lock().await;
+    if let Err(err) = config.save_to_file() {
+      error!("Failed to save refreshed sessions: {}", err);
+    }
+    if let Some(window) = app.get_webview_window("main") {
+      let _ = config.broadcast_update(&window);
+    }
+  }
+}
+
 pub async fn init(launcher_state: LauncherState, update_endpoints: Vec<Url>) -> anyhow::Result<()> {
   let title = format!("{} {}", LAUNCHER_NAME, LAUNCHER_VERSION);
 
@@ -107,19 +445,46 @@ pub async fn init(launcher_state: LauncherState, update_endpoints: Vec<Url>) ->
 
       launcher_state.game_status.set_window(main_win);
       app.manage(launcher_state);
+      tauri::async_runtime::spawn(refresh_sessions_periodically(app.handle().clone()));
 
       Ok(())
     })
     .invoke_handler(
       tauri::generate_handler![
         start_game,
+        dry_run_launch,
+        export_launch_script,
+        cancel_launch,
+        kill_game,
+        get_last_crash,
+        open_crash_report,
+        analyze_game_logs,
+        list_game_sessions,
+        read_game_session,
+        query_logs,
+        export_crash_report,
+        export_support_bundle,
         get_launcher_config,
         set_launcher_config,
         login_offline,
+        is_offline_login_allowed,
         login_msa,
+        login_msa_device,
+        cancel_msa_device_login,
+        switch_account,
+        rename_account,
+        remove_account,
+        logout,
+        refresh_account,
+        get_player_profile,
+        upload_skin,
+        reset_skin,
+        set_cape,
         fetch_modpack_info,
         get_system_memory,
+        get_memory_info,
         get_default_jre_flags,
+        validate_jre_flags,
         get_game_status
       ]
     )
@@ -4,3 +4,4 @@ pub mod game_status;
 pub mod state;
 pub mod error;
 pub mod msa_auth;
+pub mod clock;
@@ -1,7 +1,7 @@
-use std::{ borrow::Cow, collections::HashMap, sync::{ Arc, Mutex } };
+use std::{ borrow::Cow, collections::HashMap, fs, io, path::PathBuf, time::Duration };
 
-use chrono::Utc;
-use log::debug;
+use chrono::{ serde::ts_milliseconds, DateTime, TimeDelta, Utc };
+use log::{ debug, warn };
 use oauth2::{
   basic::{ BasicClient, BasicTokenType },
   reqwest::async_http_client,
@@ -10,39 +10,54 @@ use oauth2::{
   AuthorizationCode,
   ClientId,
   CsrfToken,
+  DeviceAuthorizationUrl,
   EmptyExtraTokenFields,
   PkceCodeChallenge,
   RedirectUrl,
   Scope,
+  StandardDeviceAuthorizationResponse,
   StandardTokenResponse,
   TokenResponse,
   TokenUrl,
 };
 use reqwest::Url;
 use serde::{ Deserialize, Serialize };
-use tauri::{ Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent };
+use tauri::{ AppHandle, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent };
 use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-use crate::constants::{ AUTHORIZE_URL, MSA_CLIENT_ID, REDIRECT_URL, TOKEN_URL };
+use crate::constants::{ AUTHORIZE_URL, DEVICE_CODE_URL, LAUNCHER_DIRECTORY, MSA_CLIENT_ID, REDIRECT_URL, TOKEN_URL };
 
-use super::error::StdError;
+use super::{ clock::{ is_expired, Clock, SystemClock }, error::StdError };
 
-pub async fn show_microsoft_prompt(owner_window: &WebviewWindow) -> Result<MSAuthToken, StdError> {
-  // Generate auth link and pkce challenge
+const MSA_SCOPE: &str = "XboxLive.signin offline_access";
+
+fn oauth_client() -> Result<BasicClient, StdError> {
   let client = BasicClient::new(
     ClientId::new(MSA_CLIENT_ID.to_string()),
     None,
     AuthUrl::new(AUTHORIZE_URL.to_string())?,
     Some(TokenUrl::new(TOKEN_URL.to_string())?)
-  )
-    .set_auth_type(AuthType::RequestBody)
-    .set_redirect_uri(RedirectUrl::new(REDIRECT_URL.to_string())?);
+  ).set_auth_type(AuthType::RequestBody);
+  Ok(client)
+}
+
+enum LoginWindowEvent {
+  Redirect(Url),
+  Closed,
+}
+
+/// Opens the microsoft login page in a new window and waits until it redirects back, the user closes it or `timeout` passes
+pub async fn show_microsoft_prompt(owner_window: &WebviewWindow, timeout: Duration) -> Result<MSAuthToken, StdError> {
+  // Generate auth link and pkce challenge
+  let client = oauth_client()?.set_redirect_uri(RedirectUrl::new(REDIRECT_URL.to_string())?);
 
   let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
 
   let (auth_link, csrf_state) = client
     .authorize_url(CsrfToken::new_random)
-    .add_scope(Scope::new("XboxLive.signin offline_access".to_string()))
+    .add_scope(Scope::new(MSA_SCOPE.to_string()))
     .set_pkce_challenge(pkce_code_challenge)
     .add_extra_param("prompt", "select_account")
     .add_extra_param("cobrandid", "8058f65d-ce06-4c30-9559-473c9275a65d") // Adds the Minecraft branding to the login page
@@ -51,8 +66,10 @@ pub async fn show_microsoft_prompt(owner_window: &WebviewWindow) -> Result<MSAut
   debug!("Auth link: {}", auth_link);
 
   // Open window and wait for redirect
+  let (sender, mut events) = mpsc::unbounded_channel();
   let window = {
     let app_handle = owner_window.app_handle();
+    let sender = sender.clone();
     WebviewWindowBuilder::new(app_handle, "msa_auth", WebviewUrl::External(auth_link))
       .title("Login with Microsoft")
       .maximizable(false)
@@ -60,43 +77,29 @@ pub async fn show_microsoft_prompt(owner_window: &WebviewWindow) -> Result<MSAut
       .max_inner_size(500.0, 650.0)
       .focused(true)
       .owner(owner_window)?
+      .data_directory(webview_data_dir())
+      .on_navigation(move |url| {
+        if !url.as_str().starts_with(REDIRECT_URL) {
+          return true;
+        }
+        let _ = sender.send(LoginWindowEvent::Redirect(url.clone()));
+        false
+      })
       .build()?
   };
-
-  let is_window_closed = Arc::new(Mutex::new(false));
-  {
-    let is_window_closed = Arc::clone(&is_window_closed);
-    window.on_window_event(move |event| {
-      if let WindowEvent::CloseRequested { api, .. } = event {
-        *is_window_closed.lock().unwrap() = true;
-        api.prevent_close();
-      }
-    });
-  }
-
-  let (code, state) = (loop {
-    if is_window_closed.lock().is_ok_and(|closed| *closed) {
-      let _ = window.close();
-      break Err(MSAuthError::LoginCancelled);
+  window.on_window_event(move |event| {
+    if let WindowEvent::CloseRequested { .. } = event {
+      let _ = sender.send(LoginWindowEvent::Closed);
     }
+  });
 
-    let url = window.url()?;
-    if !url.as_str().to_string().starts_with(REDIRECT_URL) {
-      continue;
-    }
-    let params: HashMap<Cow<str>, Cow<str>> = url.query_pairs().into_iter().collect();
-    if let (Some(code), Some(state)) = (params.get("code"), params.get("state")) {
-      let code = AuthorizationCode::new(code.to_string());
-      let state = CsrfToken::new(state.to_string());
-      break Ok((code, state));
-    } else {
-      let _ = window.close();
-      break Err(MSAuthError::UnexpectedError(format!("Couldn't extract authentication code: {}", url)));
-    }
-  })?;
-  window.close()?;
-
-  debug!("Got code: {}, state: {}", code.secret(), state.secret());
+  let result = match tokio::time::timeout(timeout, events.recv()).await {
+    Ok(Some(LoginWindowEvent::Redirect(url))) => parse_redirect(&url),
+    Ok(Some(LoginWindowEvent::Closed) | None) => Err(MSAuthError::LoginCancelled),
+    Err(_) => Err(MSAuthError::Timeout(timeout)),
+  };
+  let _ = window.close();
+  let (code, state) = result?;
 
   // Check CSRF challenge
 
@@ -106,13 +109,89 @@ pub async fn show_microsoft_prompt(owner_window: &WebviewWindow) -> Result<MSAut
 
   debug!("Exchanging code for token...");
   let tokens = client.exchange_code(code).set_pkce_verifier(pkce_code_verifier).request_async(async_http_client).await?;
-  debug!("Got token: {:?}", tokens);
-  Ok(MSATokenResponse::from(tokens).into())
+  Ok(MSATokenResponse::from(tokens).into_token(&SystemClock))
+}
+
+/// Code the user has to enter in the verification page, in another device or browser
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceCode {
+  pub user_code: String,
+  pub verification_uri: String,
+  /// Seconds until the code expires
+  pub expires_in: u64,
+}
+
+/// Logs in with the device code flow, which doesn't need a webview. `on_code` receives the code to show to the user
+/// and the token endpoint is polled until they enter it, the code expires or `cancel_token` is cancelled.
+pub async fn device_code_login(cancel_token: &CancellationToken, on_code: impl FnOnce(DeviceCode)) -> Result<MSAuthToken, StdError> {
+  let client = oauth_client()?.set_device_authorization_url(DeviceAuthorizationUrl::new(DEVICE_CODE_URL.to_string())?);
+
+  let details: StandardDeviceAuthorizationResponse = client
+    .exchange_device_code()?
+    .add_scope(Scope::new(MSA_SCOPE.to_string()))
+    .request_async(async_http_client).await?;
+  debug!("Device code expires in {:?}", details.expires_in());
+
+  on_code(DeviceCode {
+    user_code: details.user_code().secret().clone(),
+    verification_uri: details.verification_uri().to_string(),
+    expires_in: details.expires_in().as_secs(),
+  });
+
+  let tokens = tokio::select! {
+    _ = cancel_token.cancelled() => Err(MSAuthError::LoginCancelled)?,
+    tokens = client.exchange_device_access_token(&details).request_async(async_http_client, tokio::time::sleep, None) => tokens?,
+  };
+  Ok(MSATokenResponse::from(tokens).into_token(&SystemClock))
+}
+
+/// Cookies of the login window live here instead of the launcher webview's directory, so logging out can forget them
+fn webview_data_dir() -> PathBuf {
+  LAUNCHER_DIRECTORY.join("msa_webview")
+}
+
+/// Forgets the microsoft session of the login window, so the next login asks for the account again.
+/// Failures are only logged: WebView2 keeps the directory locked while a webview uses it.
+pub fn clear_webview_data() {
+  match fs::remove_dir_all(webview_data_dir()) {
+    Err(err) if err.kind() != io::ErrorKind::NotFound => warn!("Failed to remove the login window data: {}", err),
+    _ => {}
+  }
+}
+
+/// Clears the session of an open login window through the webview, since its data directory can't be removed while it's open
+pub fn clear_login_window(app: &AppHandle) {
+  if let Some(window) = app.get_webview_window("msa_auth") {
+    if let Err(err) = window.clear_all_browsing_data() {
+      warn!("Failed to clear the login window data: {}", err);
+    }
+    let _ = window.close();
+  }
+}
+
+/// Extracts the authorization code of the redirect url, or the error sent by microsoft
+fn parse_redirect(url: &Url) -> Result<(AuthorizationCode, CsrfToken), MSAuthError> {
+  let params: HashMap<Cow<str>, Cow<str>> = url.query_pairs().collect();
+  if let Some(error) = params.get("error") {
+    return Err(MSAuthError::OAuth {
+      error: error.to_string(),
+      description: params.get("error_description").map(|description| description.to_string()).unwrap_or_default(),
+    });
+  }
+  match (params.get("code"), params.get("state")) {
+    (Some(code), Some(state)) => Ok((AuthorizationCode::new(code.to_string()), CsrfToken::new(state.to_string()))),
+    _ => Err(MSAuthError::UnexpectedError(format!("Couldn't extract authentication code: {}", url))),
+  }
 }
 
 #[derive(Debug, Error)]
 pub enum MSAuthError {
   #[error("Login process was cancelled by the user")] LoginCancelled,
+  #[error("Login timed out after {} seconds", .0.as_secs())] Timeout(Duration),
+  #[error("Microsoft returned an error: {error} ({description})")] OAuth {
+    error: String,
+    description: String,
+  },
   #[error("CSRF state mismatch ({0} != {1})")] CsrfMismatch(String, String),
   #[error("Unexpected error: {0}")] UnexpectedError(String),
 }
@@ -121,16 +200,17 @@ pub enum MSAuthError {
 pub struct MSAuthToken {
   pub access_token: String,
   pub refresh_token: String,
-  pub expiration_date: i64,
+  #[serde(with = "ts_milliseconds")]
+  pub expiration_date: DateTime<Utc>,
 }
 
 impl MSAuthToken {
-  pub fn validate(&self) -> bool {
-    Utc::now().timestamp_millis() < self.expiration_date
+  pub fn is_expired(&self, clock: &impl Clock, skew: TimeDelta) -> bool {
+    is_expired(clock, self.expiration_date, skew)
   }
 
-  pub async fn refresh(&mut self, force: bool) -> Result<(), Box<dyn std::error::Error>> {
-    if self.validate() && !force {
+  pub async fn refresh(&mut self, force: bool, skew: TimeDelta) -> Result<(), Box<dyn std::error::Error>> {
+    if !self.is_expired(&SystemClock, skew) && !force {
       return Ok(());
     }
     let url = Url::parse_with_params(
@@ -150,7 +230,7 @@ impl MSAuthToken {
       .send().await?
       .error_for_status()?
       .json::<MSATokenResponse>().await?
-      .into();
+      .into_token(&SystemClock);
 
     self.access_token = access_token;
     self.refresh_token = refresh_token;
@@ -167,6 +247,16 @@ struct MSATokenResponse {
   expires_in_seconds: u64, // seconds
 }
 
+impl MSATokenResponse {
+  fn into_token(self, clock: &impl Clock) -> MSAuthToken {
+    MSAuthToken {
+      access_token: self.access_token,
+      refresh_token: self.refresh_token,
+      expiration_date: clock.now() + TimeDelta::seconds(self.expires_in_seconds as i64),
+    }
+  }
+}
+
 impl From<StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>> for MSATokenResponse {
   fn from(tokens: StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>) -> Self {
     MSATokenResponse {
@@ -177,12 +267,49 @@ impl From<StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>> for MSAT
   }
 }
 
-impl From<MSATokenResponse> for MSAuthToken {
-  fn from(val: MSATokenResponse) -> Self {
-    MSAuthToken {
-      access_token: val.access_token,
-      refresh_token: val.refresh_token,
-      expiration_date: Utc::now().timestamp() + ((val.expires_in_seconds * 1000u64) as i64),
+#[cfg(test)]
+mod tests {
+  use crate::app::clock::FixedClock;
+
+  use super::*;
+
+  #[test]
+  fn parses_redirects() {
+    let url = Url::parse(&format!("{REDIRECT_URL}?code=M.C123&state=abc")).unwrap();
+    let (code, state) = parse_redirect(&url).unwrap();
+    assert_eq!(code.secret(), "M.C123");
+    assert_eq!(state.secret(), "abc");
+
+    let url = Url::parse(&format!("{REDIRECT_URL}?error=access_denied&error_description=The+user+denied+access")).unwrap();
+    match parse_redirect(&url) {
+      Err(MSAuthError::OAuth { error, description }) => {
+        assert_eq!(error, "access_denied");
+        assert_eq!(description, "The user denied access");
+      }
+      other => panic!("Unexpected result: {:?}", other),
     }
+
+    assert!(matches!(parse_redirect(&Url::parse(REDIRECT_URL).unwrap()), Err(MSAuthError::UnexpectedError(_))));
+  }
+
+  #[test]
+  fn expiration_date_is_relative_to_now() {
+    let now = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").unwrap().to_utc();
+    let response = MSATokenResponse { access_token: String::new(), refresh_token: String::new(), expires_in_seconds: 3600 };
+    let token = response.into_token(&FixedClock(now));
+    assert_eq!(token.expiration_date, now + TimeDelta::hours(1));
+
+    let skew = TimeDelta::minutes(1);
+    assert!(!token.is_expired(&FixedClock(now), skew));
+    assert!(!token.is_expired(&FixedClock(now + TimeDelta::minutes(58)), skew));
+    assert!(token.is_expired(&FixedClock(now + TimeDelta::minutes(59)), skew));
+    assert!(token.is_expired(&FixedClock(now + TimeDelta::hours(2)), TimeDelta::zero()));
+  }
+
+  #[test]
+  fn reads_millisecond_expiration_dates() {
+    let json = r#"{ "access_token": "a", "refresh_token": "r", "expiration_date": 1704110400000 }"#;
+    let token: MSAuthToken = serde_json::from_str(json).unwrap();
+    assert_eq!(token.expiration_date.to_rfc3339(), "2024-01-01T12:00:00+00:00");
   }
 }
@@ -1,6 +1,7 @@
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
-use crate::{ config::LauncherConfig, modpack_downloader::ModpackDownloader };
+use crate::{ config::LauncherConfig, launch::crash_report::CrashSummary, modpack_downloader::ModpackDownloader };
 
 use super::game_status::GameStatusState;
 
@@ -8,4 +9,7 @@ pub struct LauncherState {
   pub launcher_config: Mutex<LauncherConfig>,
   pub modpack_downloader: Mutex<ModpackDownloader>,
   pub game_status: GameStatusState,
+  pub last_crash: std::sync::Mutex<Option<CrashSummary>>,
+  /// Cancels the device code login waiting for the user, if any
+  pub msa_device_login: std::sync::Mutex<Option<CancellationToken>>,
 }
@@ -0,0 +1,296 @@
+use std::{ env, io, sync::{ Arc, Mutex } };
+
+use minecraft_launcher_core::version_manager::downloader::progress::{ CallbackReporter, Event, ProgressReporter };
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+  app::{ error::StdError, game_status::GameStatus, msa_auth, state::LauncherState },
+  config::{ auth::{ Authentication, MsaMojangAuth }, offline::OfflineUuid },
+  constants::{ create_launcher_client, LAUNCHER_DIRECTORY },
+  launch::{ preview::{ LaunchPreview, ScriptFormat }, session_log::{ list_sessions, read_session, sessions_dir }, LaunchPipeline },
+  DownloadProgress,
+};
+
+const USAGE: &str = "\
+Usage: gelcorp-launcher --headless <command> [args]
+
+Commands:
+  update                  Download and install the latest modpack
+  launch                  Update the modpack and launch the game
+  dry-run [sh|bat]        Prepare the launch and print it as a script instead of starting the game
+  login-offline <name> [uuid]
+                          Log in with an offline account, with the uuid of offline servers or the given one
+  login-msa               Log in with a Microsoft account, entering a code in the browser
+  logout                  Remove the current account and its stored tokens
+  status                  Show the current account, settings and modpack
+  logs                    Print the game output of the last launch
+";
+
+/// Returns the remaining arguments if the launcher was started with `--headless`
+pub fn headless_args() -> Option<Vec<String>> {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let index = args.iter().position(|arg| arg == "--headless")?;
+  args.remove(index);
+  Some(args)
+}
+
+/// Release builds use the windows subsystem, so the parent console has to be attached manually
+#[cfg(windows)]
+pub fn attach_console() {
+  unsafe extern "system" {
+    fn AttachConsole(process_id: u32) -> i32;
+  }
+  const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+  unsafe {
+    AttachConsole(ATTACH_PARENT_PROCESS);
+  }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+#[derive(Debug, PartialEq)]
+enum CliCommand {
+  Update,
+  Launch,
+  DryRun(ScriptFormat),
+  LoginOffline {
+    username: String,
+    uuid: Option<String>,
+  },
+  LoginMsa,
+  Logout,
+  Status,
+  Logs,
+  Help,
+}
+
+impl CliCommand {
+  /// Parses the arguments after `--headless`, or `None` if they don't match any command
+  fn parse(args: &[String]) -> Option<Self> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let command = match args.as_slice() {
+      ["update"] => Self::Update,
+      ["launch"] => Self::Launch,
+      ["dry-run"] => Self::DryRun(ScriptFormat::native()),
+      ["dry-run", "sh"] => Self::DryRun(ScriptFormat::Shell),
+      ["dry-run", "bat"] => Self::DryRun(ScriptFormat::Batch),
+      ["login-offline", username] => Self::LoginOffline { username: username.to_string(), uuid: None },
+      ["login-offline", username, uuid] => Self::LoginOffline { username: username.to_string(), uuid: Some(uuid.to_string()) },
+      ["login-msa"] => Self::LoginMsa,
+      ["logout"] => Self::Logout,
+      ["status"] => Self::Status,
+      ["logs"] => Self::Logs,
+      [] | ["help"] | ["--help"] => Self::Help,
+      _ => return None,
+    };
+    Some(command)
+  }
+}
+
+pub async fn run(state: LauncherState, args: Vec<String>) -> i32 {
+  let Some(command) = CliCommand::parse(&args) else {
+    eprint!("{USAGE}");
+    return 2;
+  };
+  let result = match command {
+    CliCommand::Update => update(&state).await,
+    CliCommand::Launch => launch(&state).await,
+    CliCommand::DryRun(format) => dry_run(&state, format).await,
+    CliCommand::LoginOffline { username, uuid } => login_offline(&state, &username, uuid.as_deref()).await,
+    CliCommand::LoginMsa => login_msa(&state).await,
+    CliCommand::Logout => logout(&state).await,
+    CliCommand::Status => status(&state).await,
+    CliCommand::Logs => logs(),
+    CliCommand::Help => {
+      print!("{USAGE}");
+      Ok(())
+    }
+  };
+
+  match result {
+    Ok(()) => 0,
+    Err(err) => {
+      eprintln!("Error: {err}");
+      1
+    }
+  }
+}
+
+async fn update(state: &LauncherState) -> Result<(), StdError> {
+  let selected_options = state.launcher_config.lock().await.selected_options.clone();
+  let mut downloader = state.modpack_downloader.lock().await;
+  let pipeline = LaunchPipeline::new(LAUNCHER_DIRECTORY.clone(), terminal_reporter()).with_cancel_token(cancel_on_ctrl_c(state)?);
+  let info = pipeline.sync_modpack(&mut downloader, selected_options).await?;
+  println!("Modpack is up to date (Minecraft {}, Forge {})", info.minecraft_version, info.forge_version);
+  Ok(())
+}
+
+async fn launch(state: &LauncherState) -> Result<(), StdError> {
+  let result = LaunchPipeline::new(LAUNCHER_DIRECTORY.clone(), terminal_reporter()).with_cancel_token(cancel_on_ctrl_c(state)?).run(state).await;
+  state.game_status.set(GameStatus::Idle);
+  result
+}
+
+async fn dry_run(state: &LauncherState, format: ScriptFormat) -> Result<(), StdError> {
+  let result = LaunchPipeline::new(LAUNCHER_DIRECTORY.clone(), terminal_reporter()).with_cancel_token(cancel_on_ctrl_c(state)?).prepare(state).await;
+  state.game_status.set(GameStatus::Idle);
+  print!("{}", LaunchPreview::from(&result?).to_script(format));
+  Ok(())
+}
+
+async fn login_offline(state: &LauncherState, username: &str, uuid: Option<&str>) -> Result<(), StdError> {
+  let uuid = match uuid {
+    Some(uuid) => OfflineUuid::Fixed(Uuid::parse_str(uuid)?),
+    None => OfflineUuid::Mojang,
+  };
+  let authentication = Authentication::offline(username.to_string(), &uuid)?;
+  let mut config = state.launcher_config.lock().await;
+  config.add_account(authentication);
+  config.save_to_file()?;
+  println!("Logged in as {username} (offline)");
+  Ok(())
+}
+
+async fn login_msa(state: &LauncherState) -> Result<(), StdError> {
+  let cancel_token = CancellationToken::new();
+  let token = cancel_token.clone();
+  tokio::spawn(async move {
+    if tokio::signal::ctrl_c().await.is_ok() {
+      token.cancel();
+    }
+  });
+  let msa_token = msa_auth::device_code_login(&cancel_token, |code| {
+    println!("Open {} and enter the code {} (expires in {} minutes)", code.verification_uri, code.user_code, code.expires_in / 60);
+  }).await?;
+  let auth = MsaMojangAuth::from(&create_launcher_client(None), msa_token).await?;
+
+  let mut config = state.launcher_config.lock().await;
+  let username = config.add_account(Authentication::Msa(auth)).authentication.username().to_string();
+  config.save_to_file()?;
+  println!("Logged in as {username} (Microsoft)");
+  Ok(())
+}
+
+async fn logout(state: &LauncherState) -> Result<(), StdError> {
+  let mut config = state.launcher_config.lock().await;
+  let id = config.active_account.clone().ok_or("Not logged in")?;
+  let account = config.remove_account(&id)?;
+  config.save_to_file()?;
+  msa_auth::clear_webview_data();
+  println!("Logged out from {}", account.authentication.username());
+  Ok(())
+}
+
+async fn status(state: &LauncherState) -> Result<(), StdError> {
+  {
+    let config = state.launcher_config.lock().await;
+    match config.active_authentication() {
+      Some(auth @ Authentication::Msa(_)) => println!("Account:          {} (Microsoft)", auth.username()),
+      Some(auth @ Authentication::Offline { .. }) => println!("Account:          {} (offline)", auth.username()),
+      None => println!("Account:          not logged in"),
+    }
+    println!("Memory:           {}-{} MB", config.memory_min, config.memory_max);
+    println!("JVM flags:        {}", config.jre_flags);
+    println!("Optional mods:    {}", config.selected_options.join(", "));
+  }
+
+  let installed = LAUNCHER_DIRECTORY.join("modpack").join("modpack.enc.zip").is_file();
+  println!("Modpack installed: {}", if installed { "yes" } else { "no" });
+  let mut downloader = state.modpack_downloader.lock().await;
+  match downloader.get_or_fetch_modpack_info().await {
+    Ok(info) => println!("Latest modpack:   Minecraft {}, Forge {}", info.minecraft_version, info.forge_version),
+    Err(err) => println!("Latest modpack:   unavailable ({err})"),
+  }
+  Ok(())
+}
+
+fn logs() -> Result<(), StdError> {
+  let sessions_dir = sessions_dir(&LAUNCHER_DIRECTORY);
+  let session = list_sessions(&sessions_dir)?.into_iter().next().ok_or("No game sessions found, launch the game first")?;
+  print!("{}", read_session(&sessions_dir, &session.name)?);
+  io::Write::flush(&mut io::stdout())?;
+  Ok(())
+}
+
+/// Starts a launch that gets cancelled (or its game killed) when Ctrl+C is pressed
+fn cancel_on_ctrl_c(state: &LauncherState) -> Result<CancellationToken, StdError> {
+  let cancel_token = state.game_status.begin_launch().ok_or("A launch is already in progress")?;
+  let token = cancel_token.clone();
+  tokio::spawn(async move {
+    if tokio::signal::ctrl_c().await.is_ok() {
+      eprintln!("Cancelling...");
+      token.cancel();
+    }
+  });
+  Ok(cancel_token)
+}
+
+/// Progress reporter that prints the download progress to the terminal
+fn terminal_reporter() -> ProgressReporter {
+  let progress = Mutex::new(DownloadProgress::default());
+  Arc::new(
+    CallbackReporter::new(move |event| {
+      let progress = &mut *progress.lock().unwrap();
+      let percentage = |progress: &DownloadProgress| (progress.total > 0).then(|| (progress.current * 100) / progress.total);
+      let previous_percentage = percentage(progress);
+      let changed = match event {
+        Event::Setup { status, total } => {
+          *progress = DownloadProgress { status, current: 0, total: total.unwrap_or(0) };
+          true
+        }
+        Event::Status(status) => {
+          progress.status = status;
+          progress.total == 0
+        }
+        Event::Progress(current) => {
+          progress.current = current;
+          percentage(progress) != previous_percentage
+        }
+        Event::Total(total) => {
+          progress.total = total;
+          percentage(progress) != previous_percentage
+        }
+        _ => false,
+      };
+      if changed {
+        match percentage(progress) {
+          Some(percentage) => println!("[{percentage:>3}%] {}", progress.status),
+          None => println!("[....] {}", progress.status),
+        }
+      }
+    })
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Option<CliCommand> {
+    CliCommand::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+  }
+
+  #[test]
+  fn parses_commands() {
+    assert_eq!(parse(&[]), Some(CliCommand::Help));
+    assert_eq!(parse(&["launch"]), Some(CliCommand::Launch));
+    assert_eq!(parse(&["dry-run"]), Some(CliCommand::DryRun(ScriptFormat::native())));
+    assert_eq!(parse(&["dry-run", "bat"]), Some(CliCommand::DryRun(ScriptFormat::Batch)));
+    assert_eq!(parse(&["login-offline", "Steve"]), Some(CliCommand::LoginOffline { username: "Steve".to_string(), uuid: None }));
+    assert_eq!(
+      parse(&["login-offline", "Steve", "069a79f4-44e9-4726-a5be-fca90e38aaf5"]),
+      Some(CliCommand::LoginOffline { username: "Steve".to_string(), uuid: Some("069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string()) })
+    );
+    assert_eq!(parse(&["logs"]), Some(CliCommand::Logs));
+  }
+
+  #[test]
+  fn rejects_unknown_commands() {
+    assert_eq!(parse(&["dry-run", "ps1"]), None);
+    assert_eq!(parse(&["launch", "now"]), None);
+    assert_eq!(parse(&["login-offline"]), None);
+    assert_eq!(parse(&["play"]), None);
+  }
+}
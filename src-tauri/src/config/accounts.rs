@@ -0,0 +1,258 @@
+use log::info;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::Mutex;
+
+use crate::app::{ clock::SystemClock, error::LauncherError };
+
+use super::{ auth::Authentication, LauncherConfig };
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Account {
+  /// Uuid of the player, logging in again with the same player replaces the account
+  pub id: String,
+  /// Name chosen by the user, shown instead of the username
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub label: Option<String>,
+  pub authentication: Authentication,
+}
+
+impl Account {
+  pub fn new(authentication: Authentication) -> Self {
+    Self { id: authentication.uuid().to_string(), label: None, authentication }
+  }
+}
+
+/// Accounts refreshed by `LauncherConfig::refresh_sessions`
+pub(crate) enum RefreshTarget<'a> {
+  /// Every microsoft account that expires soon
+  Expiring,
+  /// The active account, if it expires soon
+  ActiveIfExpiring,
+  /// Forces a refresh of this account
+  Account(&'a str),
+}
+
+#[derive(Default)]
+pub(crate) struct RefreshedSessions {
+  /// Whether any account was written back to the config
+  pub changed: bool,
+  /// Username and error of every account that couldn't be refreshed
+  pub failed: Vec<(String, String)>,
+}
+
+impl RefreshedSessions {
+  /// Whether any account was written back, or the error of the first one that failed
+  pub fn into_result(self) -> Result<bool, String> {
+    match self.failed.into_iter().next() {
+      Some((_, err)) => Err(err),
+      None => Ok(self.changed),
+    }
+  }
+}
+
+impl LauncherConfig {
+  pub(crate) fn active_account(&self) -> Option<&Account> {
+    let id = self.active_account.as_ref()?;
+    self.accounts.iter().find(|account| &account.id == id)
+  }
+
+  pub(crate) fn active_authentication(&self) -> Option<&Authentication> {
+    self.active_account().map(|account| &account.authentication)
+  }
+
+  pub(crate) fn account_mut(&mut self, id: &str) -> Result<&mut Account, LauncherError> {
+    self.accounts
+      .iter_mut()
+      .find(|account| account.id == id)
+      .ok_or_else(|| LauncherError::Other(format!("Unknown account: {id}")))
+  }
+
+  /// Adds the account (or updates it if it was already added) and makes it the active one
+  pub(crate) fn add_account(&mut self, authentication: Authentication) -> &Account {
+    let account = Account::new(authentication);
+    let id = account.id.clone();
+    let index = match self.accounts.iter().position(|existing| existing.id == id) {
+      Some(index) => {
+        self.accounts[index].authentication = account.authentication;
+        index
+      }
+      None => {
+        self.accounts.push(account);
+        self.accounts.len() - 1
+      }
+    };
+    self.active_account = Some(id);
+    &self.accounts[index]
+  }
+
+  /// Removes the account, switching to another one if it was active
+  pub(crate) fn remove_account(&mut self, id: &str) -> Result<Account, LauncherError> {
+    let index = self.accounts
+      .iter()
+      .position(|account| account.id == id)
+      .ok_or_else(|| LauncherError::Other(format!("Unknown account: {id}")))?;
+    let removed = self.accounts.remove(index);
+    if self.active_account.as_deref() == Some(id) {
+      self.active_account = self.accounts.first().map(|account| account.id.clone());
+    }
+    Ok(removed)
+  }
+
+  pub(crate) fn switch_account(&mut self, id: &str) -> Result<(), LauncherError> {
+    self.account_mut(id)?;
+    self.active_account = Some(id.to_string());
+    Ok(())
+  }
+
+  pub(crate) fn rename_account(&mut self, id: &str, label: Option<String>) -> Result<(), LauncherError> {
+    let label = label.map(|label| label.trim().to_string()).filter(|label| !label.is_empty());
+    self.account_mut(id)?.label = label;
+    Ok(())
+  }
+
+  /// Copies of the microsoft accounts picked by `target`
+  fn accounts_to_refresh(&self, target: &RefreshTarget) -> Result<Vec<Account>, LauncherError> {
+    let skew = self.clock_skew();
+    let expiring = |account: &&Account| matches!(&account.authentication, Authentication::Msa(msa) if msa.needs_refresh(&SystemClock, skew));
+    let accounts = match target {
+      RefreshTarget::Expiring => self.accounts.iter().filter(expiring).cloned().collect(),
+      RefreshTarget::ActiveIfExpiring => self.active_account().filter(expiring).cloned().into_iter().collect(),
+      RefreshTarget::Account(id) => {
+        let account = self.accounts.iter().find(|account| account.id == *id).ok_or_else(|| LauncherError::Other(format!("Unknown account: {id}")))?;
+        // Offline accounts have nothing to refresh
+        matches!(account.authentication, Authentication::Msa(_)).then(|| account.clone()).into_iter().collect()
+      }
+    };
+    Ok(accounts)
+  }
+
+  /// Writes back accounts refreshed from the given copies, unless they were removed or changed (by logging in again, for example)
+  /// in the meantime. Returns whether any account was written back.
+  fn write_back_refreshed(&mut self, refreshed: Vec<(Account, Account)>) -> bool {
+    let mut changed = false;
+    for (original, account) in refreshed {
+      match self.accounts.iter_mut().find(|existing| existing.id == original.id) {
+        Some(existing) if existing.authentication == original.authentication => {
+          existing.authentication = account.authentication;
+          changed = true;
+        }
+        _ => info!("Account {} changed while its session was refreshed, keeping the newer one", original.authentication.username()),
+      }
+    }
+    changed
+  }
+
+  /// Refreshes the sessions picked by `target` without holding the config lock during the requests,
+  /// so a slow login server doesn't block every other command. The caller saves the config if it changed.
+  pub(crate) async fn refresh_sessions(
+    config: &Mutex<LauncherConfig>,
+    client: &Client,
+    target: RefreshTarget<'_>
+  ) -> Result<RefreshedSessions, LauncherError> {
+    let (accounts, skew) = {
+      let config = config.lock().await;
+      (config.accounts_to_refresh(&target)?, config.clock_skew())
+    };
+    let force = matches!(target, RefreshTarget::Account(_));
+
+    let mut result = RefreshedSessions::default();
+    let mut refreshed = vec![];
+    for original in accounts {
+      let mut account = original.clone();
+      let Authentication::Msa(msa) = &mut account.authentication else {
+        continue;
+      };
+      match msa.refresh(client, force, skew).await {
+        Ok(()) => {
+          info!("Refreshed session of {}", msa.username());
+          refreshed.push((original, account));
+        }
+        Err(err) => result.failed.push((msa.username().to_string(), err.to_string())),
+      }
+    }
+
+    if !refreshed.is_empty() {
+      result.changed = config.lock().await.write_back_refreshed(refreshed);
+    }
+    Ok(result)
+  }
+
+  /// Moves the single account of configs written by older versions to the account list
+  pub(crate) fn migrate_legacy_authentication(&mut self) -> bool {
+    match self.authentication.take() {
+      Some(authentication) => {
+        self.add_account(authentication);
+        true
+      }
+      None => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::offline::OfflineUuid;
+
+  use super::*;
+
+  #[test]
+  fn migrates_single_account_configs() {
+    let json = r#"{ "authentication": { "username": "Steve", "uuid": "00000000-0000-0000-0000-000000000001" } }"#;
+    let mut config: LauncherConfig = serde_json::from_str(json).unwrap();
+    assert!(config.migrate_legacy_authentication());
+    assert_eq!(config.accounts.len(), 1);
+    assert_eq!(config.active_authentication().unwrap().username(), "Steve");
+
+    let saved = serde_json::to_value(&config).unwrap();
+    assert!(saved.get("authentication").is_none());
+    assert_eq!(saved["active_account"], "00000000-0000-0000-0000-000000000001");
+  }
+
+  #[test]
+  fn manages_accounts() {
+    let mut config = LauncherConfig::default();
+    let steve = config.add_account(Authentication::offline("Steve".to_string(), &OfflineUuid::Mojang).unwrap()).id.clone();
+    let alex = config.add_account(Authentication::offline("Alex".to_string(), &OfflineUuid::Mojang).unwrap()).id.clone();
+    assert_eq!(config.active_account().unwrap().id, alex);
+
+    // Logging in again with the same player doesn't duplicate it
+    config.add_account(Authentication::offline("Steve".to_string(), &OfflineUuid::Mojang).unwrap());
+    assert_eq!(config.accounts.len(), 2);
+    assert_eq!(config.active_account().unwrap().id, steve);
+
+    config.rename_account(&alex, Some("  Alex (LAN)  ".to_string())).unwrap();
+    assert_eq!(config.account_mut(&alex).unwrap().label.as_deref(), Some("Alex (LAN)"));
+
+    config.switch_account(&alex).unwrap();
+    config.remove_account(&alex).unwrap();
+    assert_eq!(config.active_account().unwrap().id, steve);
+    assert!(config.switch_account(&alex).is_err());
+
+    config.remove_account(&steve).unwrap();
+    assert!(config.active_account().is_none());
+  }
+
+  #[test]
+  fn keeps_accounts_changed_during_a_refresh() {
+    let mut config = LauncherConfig::default();
+    let steve = config.add_account(Authentication::offline("Steve".to_string(), &OfflineUuid::Mojang).unwrap()).clone();
+    let alex = config.add_account(Authentication::offline("Alex".to_string(), &OfflineUuid::Mojang).unwrap()).clone();
+    let refreshed = |account: &Account| {
+      let mut refreshed = account.clone();
+      refreshed.authentication = Authentication::Offline { username: format!("{} (refreshed)", account.authentication.username()), uuid: account.authentication.uuid() };
+      (account.clone(), refreshed)
+    };
+
+    // Alex logs in again and Steve is removed while their sessions are refreshed
+    config.add_account(Authentication::Offline { username: "Alex2".to_string(), uuid: alex.authentication.uuid() });
+    config.remove_account(&steve.id).unwrap();
+    assert!(!config.write_back_refreshed(vec![refreshed(&steve), refreshed(&alex)]));
+    assert_eq!(config.accounts.len(), 1);
+    assert_eq!(config.active_authentication().unwrap().username(), "Alex2");
+
+    let current = config.active_account().unwrap().clone();
+    assert!(config.write_back_refreshed(vec![refreshed(&current)]));
+    assert_eq!(config.active_authentication().unwrap().username(), "Alex2 (refreshed)");
+  }
+}
@@ -1,14 +1,22 @@
-use chrono::Utc;
+use chrono::{ serde::ts_milliseconds, DateTime, TimeDelta, Utc };
 use log::info;
 use minecraft_launcher_core::bootstrap::auth::UserAuthentication;
-use minecraft_msa_auth::MinecraftAuthorizationFlow;
 use reqwest::Client;
 use serde::{ Deserialize, Serialize };
 use uuid::Uuid;
 
-use crate::{ app::{ error::StdError, msa_auth::MSAuthToken }, config::mojang_api_helper::PlayerProfile };
+use crate::{
+  app::{ clock::{ is_expired, Clock, SystemClock }, error::StdError, msa_auth::MSAuthToken },
+  config::{
+    credentials::Credentials,
+    minecraft_auth::{ login_with_microsoft, XBOX_LIVE },
+    mojang_api_helper::PlayerProfile,
+    offline::{ offline_login_allowed, validate_username, OfflineLoginError, OfflineUuid },
+  },
+  constants::MINECRAFT_SERVICES_URL,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Authentication {
   Msa(MsaMojangAuth),
@@ -18,6 +26,31 @@ pub enum Authentication {
   },
 }
 
+impl Authentication {
+  pub fn offline(username: String, uuid: &OfflineUuid) -> Result<Self, OfflineLoginError> {
+    if !offline_login_allowed() {
+      return Err(OfflineLoginError::Disabled);
+    }
+    validate_username(&username)?;
+    let uuid = uuid.resolve(&username);
+    Ok(Authentication::Offline { username, uuid })
+  }
+
+  pub fn uuid(&self) -> Uuid {
+    match self {
+      Authentication::Msa(MsaMojangAuth { uuid, .. }) => *uuid,
+      Authentication::Offline { uuid, .. } => *uuid,
+    }
+  }
+
+  pub fn username(&self) -> &str {
+    match self {
+      Authentication::Msa(MsaMojangAuth { username, .. }) => username,
+      Authentication::Offline { username, .. } => username,
+    }
+  }
+}
+
 impl TryInto<UserAuthentication> for Authentication {
   type Error = StdError;
   fn try_into(self) -> Result<UserAuthentication, Self::Error> {
@@ -29,42 +62,87 @@ impl TryInto<UserAuthentication> for Authentication {
           access_token: Some(moj_token),
         })
       }
-      Authentication::Offline { username, .. } => Ok(UserAuthentication::offline(&username)),
+      Authentication::Offline { username, uuid } => {
+        let mut user = UserAuthentication::offline(&username);
+        user.uuid = uuid;
+        Ok(user)
+      }
     }
   }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Sessions are refreshed this long before they expire, so the game never starts with a token about to expire
+const REFRESH_MARGIN: TimeDelta = TimeDelta::minutes(5);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct MsaMojangAuth {
   username: String,
   uuid: Uuid,
 
+  /// Tokens are kept in the credential store, they're only read from the config file of older versions
+  #[serde(default, skip_serializing)]
   moj_token: String,
-  moj_expiration_date: i64,
+  #[serde(with = "ts_milliseconds")]
+  moj_expiration_date: DateTime<Utc>,
 
+  #[serde(default, skip_serializing)]
   msa_access_token: String,
+  #[serde(default, skip_serializing)]
   msa_refresh_token: String,
-  msa_expiration_date: i64,
+  #[serde(with = "ts_milliseconds")]
+  msa_expiration_date: DateTime<Utc>,
 }
 
 impl MsaMojangAuth {
-  pub fn expired_msa(&self) -> bool {
-    Utc::now().timestamp_millis() > self.msa_expiration_date
+  pub fn username(&self) -> &str {
+    &self.username
+  }
+
+  pub fn moj_token(&self) -> &str {
+    &self.moj_token
+  }
+
+  pub fn credentials(&self) -> Credentials {
+    Credentials {
+      moj_token: self.moj_token.clone(),
+      msa_access_token: self.msa_access_token.clone(),
+      msa_refresh_token: self.msa_refresh_token.clone(),
+    }
+  }
+
+  pub fn set_credentials(&mut self, Credentials { moj_token, msa_access_token, msa_refresh_token }: Credentials) {
+    self.moj_token = moj_token;
+    self.msa_access_token = msa_access_token;
+    self.msa_refresh_token = msa_refresh_token;
+  }
+
+  /// `false` if the tokens weren't found in the credential store, the account can't be refreshed
+  pub fn has_credentials(&self) -> bool {
+    !self.msa_refresh_token.is_empty()
+  }
+
+  /// Whether any of the tokens expires within the refresh margin
+  pub fn needs_refresh(&self, clock: &impl Clock, skew: TimeDelta) -> bool {
+    self.expired_msa(clock, skew + REFRESH_MARGIN) || self.expired_mojang(clock, skew + REFRESH_MARGIN)
+  }
+
+  pub fn expired_msa(&self, clock: &impl Clock, skew: TimeDelta) -> bool {
+    is_expired(clock, self.msa_expiration_date, skew)
   }
 
-  pub async fn refresh(&mut self, force: bool) -> Result<(), StdError> {
-    if self.expired_msa() || force {
-      self.refresh_msa(force).await?;
+  pub async fn refresh(&mut self, client: &Client, force: bool, skew: TimeDelta) -> Result<(), StdError> {
+    if self.expired_msa(&SystemClock, skew) || force {
+      self.refresh_msa(force, skew).await?;
     }
-    if self.expired_mojang() || force {
-      self.refresh_mojang(force).await?;
+    if self.expired_mojang(&SystemClock, skew) || force {
+      self.refresh_mojang(client, force, skew).await?;
     }
-    self.refresh_profile().await?;
+    self.refresh_profile(client).await?;
     Ok(())
   }
 
-  pub async fn refresh_msa(&mut self, force: bool) -> Result<(), StdError> {
-    if !self.expired_msa() && !force {
+  pub async fn refresh_msa(&mut self, force: bool, skew: TimeDelta) -> Result<(), StdError> {
+    if !self.expired_msa(&SystemClock, skew) && !force {
       return Ok(());
     }
     info!("Refreshing Microsoft token...");
@@ -73,54 +151,52 @@ impl MsaMojangAuth {
       refresh_token: self.msa_refresh_token.clone(),
       expiration_date: self.msa_expiration_date,
     };
-    msa.refresh(force).await?;
+    msa.refresh(force, skew).await?;
     self.msa_access_token = msa.access_token;
     self.msa_refresh_token = msa.refresh_token;
     self.msa_expiration_date = msa.expiration_date;
     Ok(())
   }
 
-  pub fn expired_mojang(&self) -> bool {
-    Utc::now().timestamp_millis() > self.moj_expiration_date
+  pub fn expired_mojang(&self, clock: &impl Clock, skew: TimeDelta) -> bool {
+    is_expired(clock, self.moj_expiration_date, skew)
   }
 
-  pub async fn refresh_mojang(&mut self, force: bool) -> Result<(), StdError> {
-    if !self.expired_mojang() && !force {
+  pub async fn refresh_mojang(&mut self, client: &Client, force: bool, skew: TimeDelta) -> Result<(), StdError> {
+    if !self.expired_mojang(&SystemClock, skew) && !force {
       return Ok(());
     }
     info!("Refreshing Minecraft token...");
-    let mc_token = MinecraftAuthorizationFlow::new(Client::new())
-      .exchange_microsoft_token(&self.msa_access_token).await
-      .map_err(|err| format!("Failed to exchange msa token: {}", err))?;
-
-    self.username.clone_from(mc_token.username());
-    self.moj_token = mc_token.access_token().clone().into_inner();
-    self.moj_expiration_date = Utc::now().timestamp_millis() + (mc_token.expires_in() as i64) * 1000;
+    let mc_token = login_with_microsoft(client, &XBOX_LIVE, &self.msa_access_token).await?;
+    self.moj_token = mc_token.access_token;
+    self.moj_expiration_date = Utc::now() + TimeDelta::seconds(mc_token.expires_in as i64);
     Ok(())
   }
 
-  pub async fn refresh_profile(&mut self) -> Result<(), StdError> {
+  pub async fn refresh_profile(&mut self, client: &Client) -> Result<(), StdError> {
     info!("Fetching profile info...");
-    let PlayerProfile { id, name, .. } = PlayerProfile::get(&Client::new(), &self.moj_token).await?;
+    let PlayerProfile { id, name, .. } = PlayerProfile::get(client, MINECRAFT_SERVICES_URL, &self.moj_token).await?;
+    self.uuid = Uuid::parse_str(&id).map_err(|err| format!("Invalid uuid in the profile of {name}: {err}"))?;
     self.username = name;
-    self.uuid = Uuid::parse_str(&id).unwrap();
     info!("Username = {} UUID = {}", self.username, self.uuid);
     Ok(())
   }
 
-  pub async fn from(msa: MSAuthToken) -> Result<Self, StdError> {
+  pub async fn from(client: &Client, msa: MSAuthToken) -> Result<Self, StdError> {
     let mut new = Self {
       username: String::new(),
       uuid: Uuid::default(),
 
       moj_token: String::new(),
-      moj_expiration_date: 0,
+      moj_expiration_date: DateTime::UNIX_EPOCH,
 
       msa_access_token: msa.access_token,
       msa_refresh_token: msa.refresh_token,
       msa_expiration_date: msa.expiration_date,
     };
-    new.refresh(false).await?;
+    // The microsoft token was just issued, only the minecraft one is missing
+    new.refresh_mojang(client, true, TimeDelta::zero()).await?;
+    new.refresh_profile(client).await?;
     Ok(new)
   }
 }
@@ -134,3 +210,31 @@ impl From<MsaMojangAuth> for UserAuthentication {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::app::clock::FixedClock;
+
+  use super::*;
+
+  #[test]
+  fn refreshes_before_either_token_expires() {
+    let now = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").unwrap().to_utc();
+    let auth = MsaMojangAuth {
+      username: "Steve".to_string(),
+      uuid: Uuid::nil(),
+      moj_token: String::new(),
+      moj_expiration_date: now + TimeDelta::hours(24),
+      msa_access_token: String::new(),
+      msa_refresh_token: String::new(),
+      msa_expiration_date: now + TimeDelta::hours(1),
+    };
+    let skew = TimeDelta::minutes(1);
+    assert!(!auth.expired_msa(&FixedClock(now), skew));
+    assert!(!auth.needs_refresh(&FixedClock(now), skew));
+    assert!(auth.needs_refresh(&FixedClock(now + TimeDelta::minutes(55)), skew));
+    assert!(!auth.expired_msa(&FixedClock(now + TimeDelta::minutes(55)), skew));
+    assert!(auth.expired_msa(&FixedClock(now + TimeDelta::minutes(59)), skew));
+    assert!(!auth.expired_mojang(&FixedClock(now + TimeDelta::minutes(59)), skew));
+  }
+}
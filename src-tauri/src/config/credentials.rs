@@ -0,0 +1,457 @@
+use std::{ collections::HashMap, fs, io, path::{ Path, PathBuf }, sync::Mutex };
+
+use aes_gcm::{ aead::{ rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng }, Aes256Gcm, Key, Nonce };
+use log::{ error, info, warn };
+use once_cell::sync::Lazy;
+use rsa::sha2::{ Digest, Sha256 };
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+
+use crate::constants::{ LAUNCHER_DIRECTORY, LAUNCHER_NAME };
+
+use super::{ auth::Authentication, LauncherConfig };
+
+const CREDENTIALS_FILE: &str = "credentials.bin";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+pub static CREDENTIALS: Lazy<CredentialStore> = Lazy::new(|| CredentialStore::open(&LAUNCHER_DIRECTORY));
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+  #[error(transparent)] Keyring(#[from] keyring::Error),
+  #[error(transparent)] Io(#[from] io::Error),
+  #[error(transparent)] Json(#[from] serde_json::Error),
+  #[error("The credentials file is corrupted or belongs to another computer")] Decrypt,
+  #[error("Failed to get the machine id to encrypt the credentials: {0}")] MachineId(String),
+}
+
+/// Secrets of a microsoft account, never written to `launcher_config.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+  pub moj_token: String,
+  pub msa_access_token: String,
+  pub msa_refresh_token: String,
+}
+
+/// Secret storage of the os, behind a trait so the size fallback can be tested
+pub trait Keyring: Send + Sync {
+  fn get(&self, user: &str) -> keyring::Result<String>;
+  fn set(&self, user: &str, secret: &str) -> keyring::Result<()>;
+  fn delete(&self, user: &str) -> keyring::Result<()>;
+}
+
+/// Keychain, credential manager or secret service
+struct SystemKeyring;
+
+impl Keyring for SystemKeyring {
+  fn get(&self, user: &str) -> keyring::Result<String> {
+    keyring::Entry::new(LAUNCHER_NAME, user)?.get_password()
+  }
+
+  fn set(&self, user: &str, secret: &str) -> keyring::Result<()> {
+    keyring::Entry::new(LAUNCHER_NAME, user)?.set_password(secret)
+  }
+
+  fn delete(&self, user: &str) -> keyring::Result<()> {
+    match keyring::Entry::new(LAUNCHER_NAME, user)?.delete_credential() {
+      Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+      Err(err) => Err(err),
+    }
+  }
+}
+
+enum Backend {
+  /// One entry per token, since some keyrings (like the windows credential manager) only hold a couple of kilobytes each
+  Keyring(Box<dyn Keyring>),
+  /// Every account in a single encrypted file
+  File,
+}
+
+pub struct CredentialStore {
+  backend: Mutex<Backend>,
+  /// Encrypted file, used when there's no keyring or it can't hold the tokens
+  file: PathBuf,
+  /// Last credentials loaded or saved for each account, to skip writes when nothing changed
+  known: Mutex<HashMap<String, Credentials>>,
+}
+
+impl Credentials {
+  fn entries(&self) -> [(&'static str, &String); 3] {
+    [
+      ("moj_token", &self.moj_token),
+      ("msa_access_token", &self.msa_access_token),
+      ("msa_refresh_token", &self.msa_refresh_token),
+    ]
+  }
+}
+
+fn entry_user(id: &str, token: &str) -> String {
+  format!("{id}/{token}")
+}
+
+impl CredentialStore {
+  /// Uses the os keyring if it works on this system, otherwise the encrypted file in `dir`
+  pub fn open(dir: &Path) -> Self {
+    let file = dir.join(CREDENTIALS_FILE);
+    if keyring_available(&SystemKeyring) {
+      info!("Storing credentials in the system keyring");
+      Self::with_keyring(Box::new(SystemKeyring), file)
+    } else {
+      info!("Storing credentials in an encrypted file");
+      Self::file(file)
+    }
+  }
+
+  pub fn file(path: PathBuf) -> Self {
+    Self { backend: Mutex::new(Backend::File), file: path, known: Mutex::new(HashMap::new()) }
+  }
+
+  pub fn with_keyring(keyring: Box<dyn Keyring>, fallback_file: PathBuf) -> Self {
+    Self { backend: Mutex::new(Backend::Keyring(keyring)), file: fallback_file, known: Mutex::new(HashMap::new()) }
+  }
+
+  pub fn load(&self, id: &str) -> Result<Option<Credentials>, CredentialError> {
+    let from_keyring = match &*self.backend.lock().unwrap() {
+      Backend::Keyring(keyring) => load_from_keyring(keyring.as_ref(), id)?,
+      Backend::File => None,
+    };
+    // Accounts that didn't fit in the keyring were saved to the file
+    let credentials = match from_keyring {
+      Some(credentials) => Some(credentials),
+      None => read_file(&self.file)?.remove(id),
+    };
+    if let Some(credentials) = &credentials {
+      self.known.lock().unwrap().insert(id.to_string(), credentials.clone());
+    }
+    Ok(credentials)
+  }
+
+  /// Saves the given accounts and deletes the ones that were loaded or saved before and are no longer there.
+  /// Accounts that were never loaded (because loading them failed, for example) are left untouched.
+  pub fn sync(&self, accounts: HashMap<String, Credentials>) -> Result<(), CredentialError> {
+    let mut known = self.known.lock().unwrap();
+    if *known == accounts {
+      return Ok(());
+    }
+    let mut backend = self.backend.lock().unwrap();
+    if let Backend::Keyring(keyring) = &*backend {
+      match sync_keyring(keyring.as_ref(), &known, &accounts) {
+        Ok(()) => {
+          *known = accounts;
+          return Ok(());
+        }
+        Err(CredentialError::Keyring(keyring::Error::TooLong(name, limit))) => {
+          warn!("The system keyring can't hold the tokens ({name} is longer than {limit}), using an encrypted file instead");
+          for id in known.keys().chain(accounts.keys()) {
+            delete_from_keyring(keyring.as_ref(), id);
+          }
+          *backend = Backend::File;
+        }
+        Err(err) => Err(err)?,
+      }
+    }
+    let mut stored = match read_file(&self.file) {
+      Ok(stored) => stored,
+      // Nothing in it can be recovered, and keeping it would block every save
+      Err(CredentialError::Decrypt) => {
+        warn!("The credentials file can't be decrypted, replacing it");
+        HashMap::new()
+      }
+      Err(err) => Err(err)?,
+    };
+    stored.retain(|id, _| !known.contains_key(id) || accounts.contains_key(id));
+    stored.extend(accounts.iter().map(|(id, credentials)| (id.clone(), credentials.clone())));
+    write_file(&self.file, &stored)?;
+    *known = accounts;
+    Ok(())
+  }
+}
+
+fn load_from_keyring(keyring: &dyn Keyring, id: &str) -> Result<Option<Credentials>, CredentialError> {
+  let mut credentials = Credentials::default();
+  for (token, value) in [
+    ("moj_token", &mut credentials.moj_token),
+    ("msa_access_token", &mut credentials.msa_access_token),
+    ("msa_refresh_token", &mut credentials.msa_refresh_token),
+  ] {
+    match keyring.get(&entry_user(id, token)) {
+      Ok(secret) => *value = secret,
+      Err(keyring::Error::NoEntry) => return Ok(None),
+      Err(err) => Err(err)?,
+    }
+  }
+  Ok(Some(credentials))
+}
+
+fn sync_keyring(keyring: &dyn Keyring, known: &HashMap<String, Credentials>, accounts: &HashMap<String, Credentials>) -> Result<(), CredentialError> {
+  for (id, credentials) in accounts {
+    if known.get(id) != Some(credentials) {
+      for (token, value) in credentials.entries() {
+        keyring.set(&entry_user(id, token), value)?;
+      }
+    }
+  }
+  for id in known.keys().filter(|id| !accounts.contains_key(*id)) {
+    for (token, _) in Credentials::default().entries() {
+      keyring.delete(&entry_user(id, token))?;
+    }
+  }
+  Ok(())
+}
+
+/// Best effort, tokens left behind are overwritten the next time the account is saved
+fn delete_from_keyring(keyring: &dyn Keyring, id: &str) {
+  for (token, _) in Credentials::default().entries() {
+    if let Err(err) = keyring.delete(&entry_user(id, token)) {
+      warn!("Failed to delete {token} of {id} from the keyring: {err}");
+    }
+  }
+}
+
+impl LauncherConfig {
+  /// Fills the tokens of microsoft accounts, accounts of older versions already have them from the config file
+  pub(crate) fn load_credentials(&mut self) {
+    for account in &mut self.accounts {
+      let Authentication::Msa(msa) = &mut account.authentication else {
+        continue;
+      };
+      if msa.has_credentials() {
+        continue;
+      }
+      match CREDENTIALS.load(&account.id) {
+        Ok(Some(credentials)) => msa.set_credentials(credentials),
+        Ok(None) => warn!("No stored credentials for {}, log in again", msa.username()),
+        Err(err) => error!("Failed to load credentials of {}: {}", msa.username(), err),
+      }
+    }
+  }
+
+  /// Saves the tokens of every microsoft account and forgets the ones of removed accounts
+  pub(crate) fn store_credentials(&self) -> Result<(), CredentialError> {
+    let accounts = self.accounts
+      .iter()
+      .filter_map(|account| match &account.authentication {
+        Authentication::Msa(msa) if msa.has_credentials() => Some((account.id.clone(), msa.credentials())),
+        _ => None,
+      })
+      .collect();
+    CREDENTIALS.sync(accounts)
+  }
+}
+
+/// Microsoft tokens are a couple of kilobytes long, the keyring must hold secrets at least this long
+const KEYRING_PROBE_LEN: usize = 4096;
+
+fn keyring_available(keyring: &dyn Keyring) -> bool {
+  let user = "keyring-probe";
+  let probe = || -> keyring::Result<()> {
+    keyring.set(user, &"0".repeat(KEYRING_PROBE_LEN))?;
+    keyring.get(user)?;
+    keyring.delete(user)
+  };
+  match probe() {
+    Ok(()) => true,
+    Err(err) => {
+      warn!("System keyring unavailable: {}", err);
+      let _ = keyring.delete(user);
+      false
+    }
+  }
+}
+
+/// The key is derived from the machine id and a random salt stored with the data, so the file is useless on another computer.
+/// There's no weaker fallback: without a machine id the tokens aren't stored at all.
+fn derive_key(salt: &[u8]) -> Result<Key<Aes256Gcm>, CredentialError> {
+  let machine_id = machine_uid::get().map_err(|err| CredentialError::MachineId(err.to_string()))?;
+  let mut hasher = Sha256::new();
+  hasher.update(LAUNCHER_NAME.as_bytes());
+  hasher.update(machine_id.as_bytes());
+  hasher.update(salt);
+  Ok(hasher.finalize())
+}
+
+fn read_file(path: &Path) -> Result<HashMap<String, Credentials>, CredentialError> {
+  let data = match fs::read(path) {
+    Ok(data) => data,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+    Err(err) => Err(err)?,
+  };
+  if data.len() < SALT_LEN + NONCE_LEN {
+    return Err(CredentialError::Decrypt);
+  }
+  let (salt, rest) = data.split_at(SALT_LEN);
+  let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+  let json = Aes256Gcm::new(&derive_key(salt)?)
+    .decrypt(Nonce::from_slice(nonce), ciphertext)
+    .map_err(|_| CredentialError::Decrypt)?;
+  Ok(serde_json::from_slice(&json)?)
+}
+
+fn write_file(path: &Path, accounts: &HashMap<String, Credentials>) -> Result<(), CredentialError> {
+  let mut salt = [0u8; SALT_LEN];
+  OsRng.fill_bytes(&mut salt);
+  let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+  let ciphertext = Aes256Gcm::new(&derive_key(&salt)?)
+    .encrypt(&nonce, serde_json::to_vec(accounts)?.as_slice())
+    .map_err(|_| CredentialError::Decrypt)?;
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  // Written aside and renamed, so a failed write never destroys the stored tokens
+  let tmp_path = path.with_extension("tmp");
+  fs::write(&tmp_path, [&salt[..], &nonce[..], &ciphertext].concat())?;
+  fs::rename(tmp_path, path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{ env::temp_dir, sync::Arc };
+
+  use super::*;
+
+  fn credentials(token: &str) -> Credentials {
+    Credentials { moj_token: token.to_string(), msa_access_token: "access".to_string(), msa_refresh_token: "refresh".to_string() }
+  }
+
+  /// Sizes of real tokens: the minecraft one is a jwt, the microsoft ones are long opaque tickets
+  fn realistic_credentials(seed: char) -> Credentials {
+    Credentials {
+      moj_token: seed.to_string().repeat(1100),
+      msa_access_token: seed.to_string().repeat(2300),
+      msa_refresh_token: seed.to_string().repeat(1700),
+    }
+  }
+
+  /// Keyring that rejects secrets over `limit` characters, like the windows credential manager (2560 bytes of utf-16)
+  struct LimitedKeyring {
+    limit: usize,
+    entries: Arc<Mutex<HashMap<String, String>>>,
+  }
+
+  impl Keyring for LimitedKeyring {
+    fn get(&self, user: &str) -> keyring::Result<String> {
+      self.entries.lock().unwrap().get(user).cloned().ok_or(keyring::Error::NoEntry)
+    }
+
+    fn set(&self, user: &str, secret: &str) -> keyring::Result<()> {
+      if secret.len() > self.limit {
+        return Err(keyring::Error::TooLong("password".to_string(), self.limit as u32));
+      }
+      self.entries.lock().unwrap().insert(user.to_string(), secret.to_string());
+      Ok(())
+    }
+
+    fn delete(&self, user: &str) -> keyring::Result<()> {
+      self.entries.lock().unwrap().remove(user);
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn stores_each_token_in_its_own_keyring_entry() {
+    let entries = Arc::new(Mutex::new(HashMap::new()));
+    let keyring = LimitedKeyring { limit: 4096, entries: entries.clone() };
+    assert!(keyring_available(&keyring));
+
+    let path = temp_dir().join("credentials-keyring.bin");
+    let _ = fs::remove_file(&path);
+    let store = CredentialStore::with_keyring(Box::new(keyring), path.clone());
+    store.sync(HashMap::from([("a".to_string(), realistic_credentials('a'))])).unwrap();
+    assert_eq!(entries.lock().unwrap().len(), 3);
+    assert!(!path.exists());
+    assert_eq!(store.load("a").unwrap(), Some(realistic_credentials('a')));
+
+    store.sync(HashMap::new()).unwrap();
+    assert!(entries.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn falls_back_to_the_file_when_tokens_dont_fit() {
+    let entries = Arc::new(Mutex::new(HashMap::new()));
+    // 2560 bytes of utf-16
+    let keyring = LimitedKeyring { limit: 1280, entries: entries.clone() };
+    assert!(!keyring_available(&keyring));
+
+    let path = temp_dir().join("credentials-fallback.bin");
+    let _ = fs::remove_file(&path);
+    let store = CredentialStore::with_keyring(Box::new(keyring), path.clone());
+    store.sync(HashMap::from([("a".to_string(), realistic_credentials('a'))])).unwrap();
+    assert!(entries.lock().unwrap().is_empty());
+    assert!(path.exists());
+
+    let keyring = LimitedKeyring { limit: 1280, entries: entries.clone() };
+    let store = CredentialStore::with_keyring(Box::new(keyring), path);
+    assert_eq!(store.load("a").unwrap(), Some(realistic_credentials('a')));
+  }
+
+  #[test]
+  fn encrypted_file_round_trip() {
+    let path = temp_dir().join("credentials-round-trip.bin");
+    let _ = fs::remove_file(&path);
+
+    let store = CredentialStore::file(path.clone());
+    store.sync(HashMap::from([("a".to_string(), realistic_credentials('a')), ("b".to_string(), credentials("token-b"))])).unwrap();
+    let raw = fs::read(&path).unwrap();
+    assert!(!String::from_utf8_lossy(&raw).contains("token-b"));
+
+    let store = CredentialStore::file(path.clone());
+    assert_eq!(store.load("a").unwrap(), Some(realistic_credentials('a')));
+    assert_eq!(store.load("c").unwrap(), None);
+
+    store.sync(HashMap::from([("b".to_string(), credentials("token-b"))])).unwrap();
+    assert_eq!(CredentialStore::file(path).load("a").unwrap(), None);
+  }
+
+  #[test]
+  fn keeps_accounts_that_failed_to_load() {
+    let path = temp_dir().join("credentials-failed-load.bin");
+    let _ = fs::remove_file(&path);
+    CredentialStore::file(path.clone())
+      .sync(HashMap::from([("a".to_string(), credentials("token-a")), ("b".to_string(), realistic_credentials('b'))]))
+      .unwrap();
+
+    let store = CredentialStore::file(path.clone());
+    assert_eq!(store.load("a").unwrap(), Some(credentials("token-a")));
+    // The file can't be read while loading b, but it's fine again for the next save
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, b"unreadable").unwrap();
+    assert!(store.load("b").is_err());
+    fs::write(&path, data).unwrap();
+
+    store.sync(HashMap::from([("a".to_string(), credentials("new-token-a"))])).unwrap();
+    let store = CredentialStore::file(path);
+    assert_eq!(store.load("a").unwrap(), Some(credentials("new-token-a")));
+    assert_eq!(store.load("b").unwrap(), Some(realistic_credentials('b')));
+  }
+
+  #[test]
+  fn config_file_has_no_tokens() {
+    let json = r#"{
+      "username": "Steve", "uuid": "00000000-0000-0000-0000-000000000001",
+      "moj_token": "secret", "moj_expiration_date": 0,
+      "msa_access_token": "secret", "msa_refresh_token": "secret", "msa_expiration_date": 0
+    }"#;
+    let authentication: Authentication = serde_json::from_str(json).unwrap();
+    let Authentication::Msa(msa) = &authentication else {
+      panic!("Expected a microsoft account");
+    };
+    assert_eq!(msa.credentials().msa_refresh_token, "secret");
+    assert!(!serde_json::to_string(&authentication).unwrap().contains("secret"));
+  }
+
+  #[test]
+  fn rejects_tampered_file() {
+    let path = temp_dir().join("credentials-tampered.bin");
+    let store = CredentialStore::file(path.clone());
+    store.sync(HashMap::from([("a".to_string(), credentials("token-a"))])).unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(&path, data).unwrap();
+    assert!(matches!(CredentialStore::file(path).load("a"), Err(CredentialError::Decrypt)));
+  }
+}
@@ -0,0 +1,47 @@
+use log::LevelFilter;
+use serde::{ Deserialize, Serialize };
+
+pub const DEFAULT_BUFFER_SIZE: usize = 1000;
+const MIN_BUFFER_SIZE: usize = 100;
+const MAX_BUFFER_SIZE: usize = 50_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LoggingConfig {
+  /// Records kept in memory (and shown in the ui) for each log
+  #[serde(default = "LoggingConfig::default_buffer_size")]
+  pub buffer_size: usize,
+  /// Minimum level of the launcher logs shown in the ui
+  #[serde(default = "LoggingConfig::default_ui_level")]
+  pub ui_level: LevelFilter,
+  /// Minimum level written to `latest.log`
+  #[serde(default = "LoggingConfig::default_file_level")]
+  pub file_level: LevelFilter,
+}
+
+impl Default for LoggingConfig {
+  fn default() -> Self {
+    Self {
+      buffer_size: Self::default_buffer_size(),
+      ui_level: Self::default_ui_level(),
+      file_level: Self::default_file_level(),
+    }
+  }
+}
+
+impl LoggingConfig {
+  pub fn buffer_size(&self) -> usize {
+    self.buffer_size.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE)
+  }
+
+  fn default_buffer_size() -> usize {
+    DEFAULT_BUFFER_SIZE
+  }
+
+  fn default_ui_level() -> LevelFilter {
+    LevelFilter::Info
+  }
+
+  fn default_file_level() -> LevelFilter {
+    LevelFilter::Debug
+  }
+}
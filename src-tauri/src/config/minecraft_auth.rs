@@ -0,0 +1,222 @@
+// Xbox Live and Minecraft services authentication
+use std::time::Duration;
+
+use reqwest::{ Client, StatusCode };
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::constants::{ MINECRAFT_SERVICES_URL, XBL_AUTH_URL, XSTS_AUTH_URL };
+
+/// Timeout of each request of the login, the launcher client only limits the time to connect
+pub const AUTH_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum MinecraftAuthError {
+  #[error(transparent)] Reqwest(#[from] reqwest::Error),
+  #[error("Xbox Live didn't return a user hash")] MissingUserHash,
+  #[error("This Microsoft account doesn't have an Xbox profile, create one at xbox.com and try again")] NoXboxProfile,
+  #[error("Xbox Live is not available in the country of this account")] XboxUnavailable,
+  #[error("This account needs adult verification on the Xbox page before it can play")] AdultVerification,
+  #[error("This is a child account, an adult must add it to a Microsoft family before it can play")] ChildAccount,
+  #[error("Xbox Live rejected the account (error {0})")] Xsts(u64),
+  #[error("This account doesn't own Minecraft")] NotOwned,
+  #[error("This account owns Minecraft but doesn't have a profile yet, create it by logging in at minecraft.net")] NoProfile,
+}
+
+impl MinecraftAuthError {
+  /// Maps the `XErr` code of a rejected XSTS authorization
+  pub fn from_xsts_code(code: u64) -> Self {
+    match code {
+      2148916233 => Self::NoXboxProfile,
+      2148916235 => Self::XboxUnavailable,
+      2148916236 | 2148916237 => Self::AdultVerification,
+      2148916238 => Self::ChildAccount,
+      code => Self::Xsts(code),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XboxTokenResponse {
+  token: String,
+  display_claims: DisplayClaims,
+}
+
+#[derive(Debug, Deserialize)]
+struct DisplayClaims {
+  xui: Vec<UserClaims>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserClaims {
+  uhs: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XstsErrorResponse {
+  x_err: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MinecraftToken {
+  pub access_token: String,
+  /// Seconds
+  pub expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entitlements {
+  #[serde(default)]
+  items: Vec<Entitlement>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entitlement {
+  name: String,
+}
+
+/// Urls of the services involved in the login, only changed by tests
+pub struct AuthEndpoints<'a> {
+  pub xbl_url: &'a str,
+  pub xsts_url: &'a str,
+  pub services_url: &'a str,
+}
+
+pub const XBOX_LIVE: AuthEndpoints<'static> = AuthEndpoints {
+  xbl_url: XBL_AUTH_URL,
+  xsts_url: XSTS_AUTH_URL,
+  services_url: MINECRAFT_SERVICES_URL,
+};
+
+/// Exchanges a microsoft token for a minecraft one, going through Xbox Live
+pub async fn login_with_microsoft(client: &Client, endpoints: &AuthEndpoints<'_>, msa_access_token: &str) -> Result<MinecraftToken, MinecraftAuthError> {
+  let xbl: XboxTokenResponse = client
+    .post(endpoints.xbl_url)
+    .timeout(AUTH_REQUEST_TIMEOUT)
+    .json(&json!({
+      "Properties": {
+        "AuthMethod": "RPS",
+        "SiteName": "user.auth.xboxlive.com",
+        "RpsTicket": format!("d={msa_access_token}"),
+      },
+      "RelyingParty": "http://auth.xboxlive.com",
+      "TokenType": "JWT",
+    }))
+    .send().await?
+    .error_for_status()?
+    .json().await?;
+
+  let response = client
+    .post(endpoints.xsts_url)
+    .timeout(AUTH_REQUEST_TIMEOUT)
+    .json(&json!({
+      "Properties": {
+        "SandboxId": "RETAIL",
+        "UserTokens": [xbl.token],
+      },
+      "RelyingParty": "rp://api.minecraftservices.com/",
+      "TokenType": "JWT",
+    }))
+    .send().await?;
+  if response.status() == StatusCode::UNAUTHORIZED {
+    let XstsErrorResponse { x_err } = response.json().await?;
+    return Err(MinecraftAuthError::from_xsts_code(x_err));
+  }
+  let xsts: XboxTokenResponse = response.error_for_status()?.json().await?;
+  let user_hash = xsts.display_claims.xui.into_iter().next().ok_or(MinecraftAuthError::MissingUserHash)?.uhs;
+
+  let token = client
+    .post(format!("{}/authentication/login_with_xbox", endpoints.services_url))
+    .timeout(AUTH_REQUEST_TIMEOUT)
+    .json(&json!({ "identityToken": format!("XBL3.0 x={user_hash};{}", xsts.token) }))
+    .send().await?
+    .error_for_status()?
+    .json().await?;
+  Ok(token)
+}
+
+/// Explains why an account has no profile: it either doesn't own the game or never created the profile
+pub async fn missing_profile_error(client: &Client, services_url: &str, mojang_token: &str) -> MinecraftAuthError {
+  let entitlements = async {
+    client
+      .get(format!("{services_url}/entitlements/mcstore"))
+      .timeout(AUTH_REQUEST_TIMEOUT)
+      .bearer_auth(mojang_token)
+      .send().await?
+      .error_for_status()?
+      .json::<Entitlements>().await
+  };
+  match entitlements.await {
+    Ok(entitlements) if entitlements.items.iter().any(|item| item.name == "product_minecraft" || item.name == "game_minecraft") =>
+      MinecraftAuthError::NoProfile,
+    Ok(_) => MinecraftAuthError::NotOwned,
+    Err(err) => err.into(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn maps_xsts_codes() {
+    assert!(matches!(MinecraftAuthError::from_xsts_code(2148916233), MinecraftAuthError::NoXboxProfile));
+    assert!(matches!(MinecraftAuthError::from_xsts_code(2148916238), MinecraftAuthError::ChildAccount));
+    assert!(matches!(MinecraftAuthError::from_xsts_code(1), MinecraftAuthError::Xsts(1)));
+  }
+
+  async fn login(server: &mockito::Server) -> Result<MinecraftToken, MinecraftAuthError> {
+    let xbl_url = format!("{}/user/authenticate", server.url());
+    let xsts_url = format!("{}/xsts/authorize", server.url());
+    let services_url = server.url();
+    let endpoints = AuthEndpoints { xbl_url: &xbl_url, xsts_url: &xsts_url, services_url: &services_url };
+    login_with_microsoft(&Client::new(), &endpoints, "msa-token").await
+  }
+
+  #[tokio::test]
+  async fn logs_in_through_xbox_live() {
+    let mut server = mockito::Server::new_async().await;
+    server
+      .mock("POST", "/user/authenticate")
+      .match_body(mockito::Matcher::PartialJson(json!({ "Properties": { "RpsTicket": "d=msa-token" } })))
+      .with_body(r#"{ "Token": "xbl-token", "DisplayClaims": { "xui": [{ "uhs": "hash" }] } }"#)
+      .create_async().await;
+    server
+      .mock("POST", "/xsts/authorize")
+      .match_body(mockito::Matcher::PartialJson(json!({ "Properties": { "UserTokens": ["xbl-token"] } })))
+      .with_body(r#"{ "Token": "xsts-token", "DisplayClaims": { "xui": [{ "uhs": "hash" }] } }"#)
+      .create_async().await;
+    server
+      .mock("POST", "/authentication/login_with_xbox")
+      .match_body(mockito::Matcher::Json(json!({ "identityToken": "XBL3.0 x=hash;xsts-token" })))
+      .with_body(r#"{ "access_token": "mc-token", "expires_in": 86400 }"#)
+      .create_async().await;
+
+    let token = login(&server).await.unwrap();
+    assert_eq!(token.access_token, "mc-token");
+    assert_eq!(token.expires_in, 86400);
+  }
+
+  #[tokio::test]
+  async fn explains_rejected_xsts_authorizations() {
+    let mut server = mockito::Server::new_async().await;
+    server
+      .mock("POST", "/user/authenticate")
+      .with_body(r#"{ "Token": "xbl-token", "DisplayClaims": { "xui": [{ "uhs": "hash" }] } }"#)
+      .create_async().await;
+
+    for (code, expected) in [(2148916233u64, "NoXboxProfile"), (2148916238, "ChildAccount"), (2148916227, "Xsts(2148916227)")] {
+      let xsts = server
+        .mock("POST", "/xsts/authorize")
+        .with_status(401)
+        .with_body(format!(r#"{{ "Identity": "0", "XErr": {code}, "Message": "", "Redirect": "https://start.ui.xboxlive.com/CreateAccount" }}"#))
+        .create_async().await;
+      let err = login(&server).await.unwrap_err();
+      assert_eq!(format!("{err:?}"), expected);
+      xsts.remove_async().await;
+    }
+  }
+}
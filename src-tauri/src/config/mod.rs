@@ -1,41 +1,70 @@
+pub mod accounts;
 pub mod auth;
-mod mojang_api_helper;
+pub mod credentials;
+pub mod logging;
+pub mod minecraft_auth;
+pub mod mojang_api_helper;
+pub mod offline;
 
 use std::{ fs::{ create_dir_all, File }, path::PathBuf };
 
+use chrono::TimeDelta;
 use log::{ error, info };
 use serde::{ Deserialize, Serialize };
 use tauri::{ Emitter, WebviewWindow };
 
-use crate::{ app::error::StdError, constants::{ G1GC_JRE_FLAGS, LAUNCHER_DIRECTORY } };
+use crate::{ app::error::StdError, constants::{ create_launcher_client, G1GC_JRE_FLAGS, LAUNCHER_DIRECTORY }, launch::jvm_args::take_memory_flags };
 
-use self::auth::Authentication;
+use self::{ accounts::Account, auth::Authentication, logging::LoggingConfig, offline::offline_login_allowed };
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LauncherConfig {
-  #[serde(default, skip_serializing_if = "Option::is_none")]
+  /// Single account of older versions, moved to `accounts` when loading
+  #[serde(default, skip_serializing)]
   pub(crate) authentication: Option<Authentication>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub(crate) accounts: Vec<Account>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) active_account: Option<String>,
 
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub(crate) selected_options: Vec<String>,
   #[serde(default = "LauncherConfig::default_providers", skip_serializing_if = "Vec::is_empty")]
   pub(crate) providers: Vec<String>,
 
+  #[serde(default = "LauncherConfig::default_memory_min")]
+  pub(crate) memory_min: u32,
   #[serde(default = "LauncherConfig::default_memory_max")]
-  pub(crate) memory_max: u16,
+  pub(crate) memory_max: u32,
 
   #[serde(default = "LauncherConfig::default_jre_flags")]
   pub(crate) jre_flags: String,
+
+  #[serde(default)]
+  pub(crate) logging: LoggingConfig,
+
+  /// Seconds to wait for the user to finish logging in the microsoft login window
+  #[serde(default = "LauncherConfig::default_msa_login_timeout")]
+  pub(crate) msa_login_timeout: u64,
+  /// Seconds before their expiration date that tokens are refreshed, in case the clock runs behind
+  #[serde(default = "LauncherConfig::default_clock_skew_margin")]
+  pub(crate) clock_skew_margin: u64,
 }
 
 impl Default for LauncherConfig {
   fn default() -> Self {
     Self {
       authentication: None,
+      accounts: vec![],
+      active_account: None,
       selected_options: vec![],
       providers: LauncherConfig::default_providers(),
+      memory_min: LauncherConfig::default_memory_min(),
       memory_max: LauncherConfig::default_memory_max(),
       jre_flags: LauncherConfig::default_jre_flags(),
+      logging: LoggingConfig::default(),
+      msa_login_timeout: LauncherConfig::default_msa_login_timeout(),
+      clock_skew_margin: LauncherConfig::default_clock_skew_margin(),
     }
   }
 }
@@ -50,39 +79,82 @@ impl LauncherConfig {
       .ok()
       .and_then(|mut f| serde_json::from_reader(&mut f).ok())
       .unwrap_or_default();
+    if config.migrate_legacy_authentication() {
+      info!("Moved the stored account to the account list");
+    }
+    if config.migrate_memory_flags() {
+      info!("Moved the memory options of the java arguments to the memory settings");
+    }
+    if !offline_login_allowed() {
+      config.accounts.retain(|account| matches!(account.authentication, Authentication::Msa(_)));
+      if config.active_account().is_none() {
+        config.active_account = config.accounts.first().map(|account| account.id.clone());
+      }
+    }
+    config.load_credentials();
     config.validate_session().await;
-    let _ = config.save_to_file();
+    if let Err(err) = config.save_to_file() {
+      error!("Failed to save the launcher config: {}", err);
+    }
     config
   }
 
+  /// Refreshes the tokens of every microsoft account. Accounts that fail are kept, so they can be refreshed later.
   pub(crate) async fn validate_session(&mut self) {
-    if let Some(Authentication::Msa(msa)) = self.authentication.as_mut() {
-      if let Err(err) = msa.refresh(false).await {
-        error!("Failed to refresh msa token: {}", err);
-        self.authentication = None;
-        let _ = self.save_to_file();
-      } else {
-        info!("Logged in successfully to msa");
+    let skew = self.clock_skew();
+    let client = create_launcher_client(None);
+    for account in &mut self.accounts {
+      if let Authentication::Msa(msa) = &mut account.authentication {
+        match msa.refresh(&client, false, skew).await {
+          Ok(()) => info!("Refreshed msa session of {}", msa.username()),
+          Err(err) => error!("Failed to refresh msa token of {}: {}", msa.username(), err),
+        }
       }
     }
   }
 
+  /// Stores the tokens first: the config file doesn't have them, so it's only written once they're safe
   pub(crate) fn save_to_file(&self) -> Result<(), StdError> {
+    self.store_credentials()?;
     let path = Self::get_file_path();
-    let mut file = File::create(&path)?;
     if let Some(parent) = path.parent() {
       create_dir_all(parent)?;
     }
+    let mut file = File::create(&path)?;
     serde_json::to_writer_pretty(&mut file, &self)?;
     Ok(())
   }
 
+  /// Older versions allowed `-Xms`/`-Xmx` in the java arguments, which are rejected now that memory has its own settings
+  fn migrate_memory_flags(&mut self) -> bool {
+    let Some(flags) = take_memory_flags(&self.jre_flags) else {
+      return false;
+    };
+    self.jre_flags = flags.jre_flags;
+    if let Some(max) = flags.max {
+      self.memory_max = max;
+    }
+    if let Some(min) = flags.min {
+      self.memory_min = min;
+    }
+    self.memory_min = self.memory_min.min(self.memory_max);
+    true
+  }
+
+  pub(crate) fn clock_skew(&self) -> TimeDelta {
+    TimeDelta::seconds(self.clock_skew_margin as i64)
+  }
+
   pub(crate) fn broadcast_update(&self, window: &WebviewWindow) -> Result<(), StdError> {
     window.emit("launcher_config_update", &self)?;
     Ok(())
   }
 
-  fn default_memory_max() -> u16 {
+  fn default_memory_min() -> u32 {
+    512
+  }
+
+  fn default_memory_max() -> u32 {
     1536
   }
 
@@ -90,6 +162,14 @@ impl LauncherConfig {
     G1GC_JRE_FLAGS.to_owned()
   }
 
+  fn default_msa_login_timeout() -> u64 {
+    10 * 60
+  }
+
+  fn default_clock_skew_margin() -> u64 {
+    60
+  }
+
   fn default_providers() -> Vec<String> {
     env!("DEFAULT_PROVIDERS")
       .split(' ')
@@ -1,7 +1,8 @@
-use reqwest::Client;
+use reqwest::{ multipart::{ Form, Part }, Client, StatusCode };
 use serde::{ Deserialize, Serialize };
+use serde_json::json;
 
-use crate::app::error::StdError;
+use super::minecraft_auth::{ missing_profile_error, MinecraftAuthError, AUTH_REQUEST_TIMEOUT };
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PlayerProfile {
@@ -14,14 +15,66 @@ pub struct PlayerProfile {
 }
 
 impl PlayerProfile {
-  pub async fn get(client: &Client, mojang_token: &str) -> Result<PlayerProfile, StdError> {
+  pub async fn get(client: &Client, services_url: &str, mojang_token: &str) -> Result<PlayerProfile, MinecraftAuthError> {
+    let response = client
+      .get(format!("{services_url}/minecraft/profile"))
+      .timeout(AUTH_REQUEST_TIMEOUT)
+      .bearer_auth(mojang_token)
+      .send().await?;
+    // Game pass accounts can play without entitlements, so ownership is only checked when there's no profile
+    if response.status() == StatusCode::NOT_FOUND {
+      return Err(missing_profile_error(client, services_url, mojang_token).await);
+    }
+    Ok(response.error_for_status()?.json().await?)
+  }
+
+  /// Uploads a png skin, returning the updated profile
+  pub async fn upload_skin(client: &Client, services_url: &str, mojang_token: &str, variant: SkinType, png: Vec<u8>) -> reqwest::Result<PlayerProfile> {
+    let form = Form::new()
+      .text("variant", variant.as_str())
+      .part("file", Part::bytes(png).file_name("skin.png").mime_str("image/png")?);
+    client
+      .post(format!("{services_url}/minecraft/profile/skins"))
+      .bearer_auth(mojang_token)
+      .multipart(form)
+      .send().await?
+      .error_for_status()?
+      .json().await
+  }
+
+  /// Goes back to the default skin
+  pub async fn reset_skin(client: &Client, services_url: &str, mojang_token: &str) -> reqwest::Result<PlayerProfile> {
     client
-      .get("https://api.minecraftservices.com/minecraft/profile")
+      .delete(format!("{services_url}/minecraft/profile/skins/active"))
       .bearer_auth(mojang_token)
       .send().await?
       .error_for_status()?
       .json().await
-      .map_err(|err| format!("Failed to get deserialize profile: {}", err).into())
+  }
+
+  /// Shows one of the capes of the player, or hides the active one with `None`
+  pub async fn set_cape(client: &Client, services_url: &str, mojang_token: &str, cape_id: Option<&str>) -> reqwest::Result<PlayerProfile> {
+    let url = format!("{services_url}/minecraft/profile/capes/active");
+    let request = match cape_id {
+      Some(cape_id) => client.put(url).json(&json!({ "capeId": cape_id })),
+      None => client.delete(url),
+    };
+    request.bearer_auth(mojang_token).send().await?.error_for_status()?.json().await
+  }
+}
+
+/// Checks that the file is a png with the size of a skin (64x64, or 64x32 for old skins)
+pub fn validate_skin(png: &[u8]) -> Result<(), String> {
+  const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+  // The IHDR chunk is always first, with the width and height after its length and type
+  if png.len() < 24 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+    return Err("The skin must be a png image".to_string());
+  }
+  let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+  let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+  match (width, height) {
+    (64, 64) | (64, 32) => Ok(()),
+    _ => Err(format!("The skin must be 64x64 or 64x32, not {width}x{height}")),
   }
 }
 
@@ -39,6 +92,15 @@ pub enum SkinType {
   Classic,
 }
 
+impl SkinType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SkinType::Slim => "slim",
+      SkinType::Classic => "classic",
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Cape {
   pub alias: String,
@@ -56,3 +118,91 @@ pub struct Skin {
   pub url: String,
   pub variant: Option<SkinType>,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PROFILE: &str = r#"{
+    "id": "069a79f444e94726a5befca90e38aaf5",
+    "name": "Notch",
+    "skins": [{ "id": "s1", "state": "ACTIVE", "textureKey": "abc", "url": "http://textures.minecraft.net/texture/abc", "variant": "SLIM" }],
+    "capes": [{ "id": "c1", "state": "ACTIVE", "alias": "Migrator", "url": "http://textures.minecraft.net/texture/def" }]
+  }"#;
+
+  fn skin_png(width: u32, height: u32) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&width.to_be_bytes());
+    png.extend_from_slice(&height.to_be_bytes());
+    png
+  }
+
+  #[test]
+  fn validates_skin_size() {
+    assert!(validate_skin(&skin_png(64, 64)).is_ok());
+    assert!(validate_skin(&skin_png(64, 32)).is_ok());
+    assert!(validate_skin(&skin_png(128, 128)).is_err());
+    assert!(validate_skin(b"GIF89a not a png at all").is_err());
+  }
+
+  #[tokio::test]
+  async fn manages_skins_and_capes() {
+    let mut server = mockito::Server::new_async().await;
+    let upload = server
+      .mock("POST", "/minecraft/profile/skins")
+      .match_header("authorization", "Bearer token")
+      .match_body(mockito::Matcher::Regex("name=\"variant\"\r\n\r\nslim".to_string()))
+      .with_body(PROFILE)
+      .create_async().await;
+    let reset = server.mock("DELETE", "/minecraft/profile/skins/active").with_body(PROFILE).create_async().await;
+    let show_cape = server
+      .mock("PUT", "/minecraft/profile/capes/active")
+      .match_body(mockito::Matcher::Json(json!({ "capeId": "c1" })))
+      .with_body(PROFILE)
+      .create_async().await;
+    let hide_cape = server.mock("DELETE", "/minecraft/profile/capes/active").with_body(PROFILE).create_async().await;
+
+    let client = Client::new();
+    let profile = PlayerProfile::upload_skin(&client, &server.url(), "token", SkinType::Slim, skin_png(64, 64)).await.unwrap();
+    assert_eq!(profile.skins[0].texture_key, "abc");
+    assert_eq!(profile.capes[0].alias, "Migrator");
+    PlayerProfile::reset_skin(&client, &server.url(), "token").await.unwrap();
+    PlayerProfile::set_cape(&client, &server.url(), "token", Some("c1")).await.unwrap();
+    PlayerProfile::set_cape(&client, &server.url(), "token", None).await.unwrap();
+
+    for mock in [upload, reset, show_cape, hide_cape] {
+      mock.assert_async().await;
+    }
+  }
+
+  #[tokio::test]
+  async fn gets_profiles() {
+    let mut server = mockito::Server::new_async().await;
+    server
+      .mock("GET", "/minecraft/profile")
+      .match_header("authorization", "Bearer token")
+      .with_body(r#"{ "id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch" }"#)
+      .create_async().await;
+
+    let profile = PlayerProfile::get(&Client::new(), &server.url(), "token").await.unwrap();
+    assert_eq!(profile.name, "Notch");
+  }
+
+  #[tokio::test]
+  async fn explains_missing_profiles() {
+    let mut server = mockito::Server::new_async().await;
+    server.mock("GET", "/minecraft/profile").with_status(404).create_async().await;
+    let entitlements = server
+      .mock("GET", "/entitlements/mcstore")
+      .with_body(r#"{ "items": [{ "name": "product_minecraft" }, { "name": "game_minecraft" }] }"#)
+      .create_async().await;
+
+    let result = PlayerProfile::get(&Client::new(), &server.url(), "token").await;
+    assert!(matches!(result, Err(MinecraftAuthError::NoProfile)));
+
+    entitlements.remove_async().await;
+    server.mock("GET", "/entitlements/mcstore").with_body(r#"{ "items": [] }"#).create_async().await;
+    let result = PlayerProfile::get(&Client::new(), &server.url(), "token").await;
+    assert!(matches!(result, Err(MinecraftAuthError::NotOwned)));
+  }
+}
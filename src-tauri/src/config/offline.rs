@@ -0,0 +1,76 @@
+// Offline accounts, for LAN servers
+use minecraft_launcher_core::bootstrap::auth::UserAuthentication;
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum OfflineLoginError {
+  #[error("Offline accounts are disabled in this launcher")] Disabled,
+  #[error("The username must have between 3 and 16 characters")] InvalidLength,
+  #[error("The username can only have letters, numbers and underscores")] InvalidCharacters,
+}
+
+/// How the uuid of an offline player is chosen
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "scheme", content = "uuid", rename_all = "snake_case")]
+pub enum OfflineUuid {
+  /// Same uuid that offline mode servers give the player (`OfflinePlayer:<name>`), so it keeps its inventory there
+  #[default]
+  Mojang,
+  /// Uuid chosen by the user, to keep the same player after changing the name
+  Fixed(Uuid),
+}
+
+impl OfflineUuid {
+  pub fn resolve(&self, username: &str) -> Uuid {
+    match self {
+      OfflineUuid::Mojang => UserAuthentication::offline(username).uuid,
+      OfflineUuid::Fixed(uuid) => *uuid,
+    }
+  }
+}
+
+/// Offline accounts can be disabled when building with `ALLOW_OFFLINE_LOGIN=false`
+pub fn offline_login_allowed() -> bool {
+  !matches!(option_env!("ALLOW_OFFLINE_LOGIN"), Some("false" | "0"))
+}
+
+/// Checks the username against the rules of minecraft: 3 to 16 letters, numbers or underscores
+pub fn validate_username(username: &str) -> Result<(), OfflineLoginError> {
+  if !(3..=16).contains(&username.len()) {
+    return Err(OfflineLoginError::InvalidLength);
+  }
+  if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+    return Err(OfflineLoginError::InvalidCharacters);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validates_usernames() {
+    assert_eq!(validate_username("Steve_123"), Ok(()));
+    assert_eq!(validate_username("ab"), Err(OfflineLoginError::InvalidLength));
+    assert_eq!(validate_username("a_very_long_username"), Err(OfflineLoginError::InvalidLength));
+    assert_eq!(validate_username("Steve!"), Err(OfflineLoginError::InvalidCharacters));
+    assert_eq!(validate_username("Stéve"), Err(OfflineLoginError::InvalidCharacters));
+  }
+
+  #[test]
+  fn resolves_uuids() {
+    let mojang = OfflineUuid::Mojang.resolve("Steve");
+    assert_eq!(mojang.get_version_num(), 3);
+    assert_eq!(mojang, OfflineUuid::Mojang.resolve("Steve"));
+    assert_ne!(mojang, OfflineUuid::Mojang.resolve("Alex"));
+
+    let fixed = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+    assert_eq!(OfflineUuid::Fixed(fixed).resolve("Steve"), fixed);
+
+    let scheme: OfflineUuid = serde_json::from_str(r#"{ "scheme": "fixed", "uuid": "00000000-0000-0000-0000-000000000001" }"#).unwrap();
+    assert!(matches!(scheme, OfflineUuid::Fixed(uuid) if uuid == fixed));
+  }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{ path::PathBuf, time::Duration };
 
 use expand_str::expand_string_with_env;
 use minecraft_launcher_core::bootstrap::options::ProxyOptions;
@@ -18,13 +18,17 @@ pub const ZGC_JRE_FLAGS: &str = "-XX:+UseZGC -XX:+ZGenerational -XX:+UseStringDe
 pub const REDIRECT_URL: &str = "https://login.live.com/oauth20_desktop.srf";
 pub const AUTHORIZE_URL: &str = "https://login.live.com/oauth20_authorize.srf";
 pub const TOKEN_URL: &str = "https://login.live.com/oauth20_token.srf";
+pub const DEVICE_CODE_URL: &str = "https://login.live.com/oauth20_connect.srf";
+pub const XBL_AUTH_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
+pub const XSTS_AUTH_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+pub const MINECRAFT_SERVICES_URL: &str = "https://api.minecraftservices.com";
 
 pub const LAUNCHER_USER_AGENT: &str = concat!(env!("LAUNCHER_NAME"), '/', env!("CARGO_PKG_VERSION"));
 pub static LAUNCHER_DIRECTORY: Lazy<PathBuf> = Lazy::new(|| expand_string_with_env(env!("GAME_DIR_PATH")).unwrap().into());
 
 pub fn create_launcher_client(proxy: Option<ProxyOptions>) -> Client {
   let proxy = proxy.as_ref().and_then(ProxyOptions::create_http_proxy);
-  let mut builder = Client::builder().user_agent(LAUNCHER_USER_AGENT);
+  let mut builder = Client::builder().user_agent(LAUNCHER_USER_AGENT).connect_timeout(Duration::from_secs(30));
   if let Some(proxy) = proxy {
     builder = builder.proxy(proxy);
   }
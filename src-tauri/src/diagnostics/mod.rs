@@ -0,0 +1,199 @@
+pub mod support_bundle;
+
+use std::{ fs, io, path::Path };
+
+use log::{ info, warn };
+use regex::Regex;
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+
+const BUILTIN_RULES: &str = include_str!("rules.json");
+/// Rules shipped by the modpack, relative to the game directory
+pub const PACK_RULES_FILE: &str = "diagnostic_rules.json";
+
+#[derive(Debug, Error)]
+pub enum DiagnosticsError {
+  #[error("Failed to read rules: {0}")] Io(#[from] io::Error),
+  #[error("Failed to parse rules: {0}")] Json(#[from] serde_json::Error),
+  #[error("Invalid pattern in rule {id}: {source}")] Pattern {
+    id: String,
+    source: regex::Error,
+  },
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+  rules: Vec<RuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleDefinition {
+  id: String,
+  title: String,
+  /// May reference named capture groups of the patterns, like `$mod`
+  description: String,
+  fix: String,
+  patterns: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+  id: String,
+  title: String,
+  description: String,
+  fix: String,
+  patterns: Vec<Regex>,
+}
+
+impl TryFrom<RuleDefinition> for Rule {
+  type Error = DiagnosticsError;
+
+  fn try_from(def: RuleDefinition) -> Result<Self, Self::Error> {
+    let patterns = def.patterns
+      .iter()
+      .map(|pattern| Regex::new(pattern))
+      .collect::<Result<_, _>>()
+      .map_err(|source| DiagnosticsError::Pattern { id: def.id.clone(), source })?;
+    Ok(Self { id: def.id, title: def.title, description: def.description, fix: def.fix, patterns })
+  }
+}
+
+impl Rule {
+  fn check(&self, source: &str, text: &str) -> Option<Diagnosis> {
+    for line in text.lines() {
+      for pattern in &self.patterns {
+        let Some(captures) = pattern.captures(line) else {
+          continue;
+        };
+        let expand = |template: &str| {
+          let mut expanded = String::new();
+          captures.expand(template, &mut expanded);
+          expanded.trim().to_string()
+        };
+        return Some(Diagnosis {
+          id: self.id.clone(),
+          title: self.title.clone(),
+          description: expand(&self.description),
+          fix: expand(&self.fix),
+          source: source.to_string(),
+          matched_line: line.trim().to_string(),
+        });
+      }
+    }
+    None
+  }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Diagnosis {
+  pub id: String,
+  pub title: String,
+  pub description: String,
+  pub fix: String,
+  /// Where the match was found, `game_logs` or `crash_report`
+  pub source: String,
+  pub matched_line: String,
+}
+
+#[derive(Debug)]
+pub struct RuleSet {
+  rules: Vec<Rule>,
+}
+
+impl RuleSet {
+  pub fn from_json(json: &str) -> Result<Self, DiagnosticsError> {
+    let file: RuleFile = serde_json::from_str(json)?;
+    let rules = file.rules.into_iter().map(Rule::try_from).collect::<Result<_, _>>()?;
+    Ok(Self { rules })
+  }
+
+  pub fn builtin() -> Self {
+    Self::from_json(BUILTIN_RULES).expect("Built-in diagnostic rules are invalid")
+  }
+
+  /// Built-in rules plus the ones shipped with the modpack, if any
+  pub fn load(mc_dir: &Path) -> Self {
+    let mut rules = Self::builtin();
+    let pack_rules_path = mc_dir.join(PACK_RULES_FILE);
+    if !pack_rules_path.is_file() {
+      return rules;
+    }
+    match fs::read_to_string(&pack_rules_path).map_err(DiagnosticsError::from).and_then(|json| Self::from_json(&json)) {
+      Ok(pack_rules) => {
+        info!("Loaded {} diagnostic rules from the modpack", pack_rules.rules.len());
+        rules.merge(pack_rules);
+      }
+      Err(err) => warn!("Ignoring modpack diagnostic rules: {}", err),
+    }
+    rules
+  }
+
+  /// Adds the rules of `other`, replacing the ones with the same id
+  pub fn merge(&mut self, other: RuleSet) {
+    for rule in other.rules {
+      match self.rules.iter_mut().find(|existing| existing.id == rule.id) {
+        Some(existing) => *existing = rule,
+        None => self.rules.push(rule),
+      }
+    }
+  }
+
+  /// Checks every rule against the given `(source, text)` pairs, reporting each rule at most once
+  pub fn analyze(&self, sources: &[(&str, &str)]) -> Vec<Diagnosis> {
+    self.rules
+      .iter()
+      .filter_map(|rule| sources.iter().find_map(|(source, text)| rule.check(source, text)))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn builtin_rules_are_valid() {
+    assert!(!RuleSet::builtin().rules.is_empty());
+  }
+
+  #[test]
+  fn detects_known_issues() {
+    let logs =
+      "[12:00:01] [main/INFO]: Loading mods\n\
+      [12:00:02] [main/ERROR]: Missing or unsupported mandatory dependencies:\n\
+      \tMod ID: 'flywheel', Requested by: 'create', Expected range: '[0.6.9,0.6.10)', Actual version: '[MISSING]'";
+    let report = "Description: Exception in server tick loop\n\njava.lang.OutOfMemoryError: Java heap space";
+
+    let diagnoses = RuleSet::builtin().analyze(&[("game_logs", logs), ("crash_report", report)]);
+    let ids: Vec<&str> = diagnoses.iter().map(|diagnosis| diagnosis.id.as_str()).collect();
+    assert_eq!(ids, vec!["out_of_memory", "missing_dependency"]);
+
+    let missing = &diagnoses[1];
+    assert_eq!(missing.source, "game_logs");
+    assert!(missing.description.contains("create"));
+    assert!(missing.description.contains("flywheel"));
+    assert_eq!(diagnoses[0].source, "crash_report");
+  }
+
+  #[test]
+  fn pack_rules_override_builtin() {
+    let mut rules = RuleSet::builtin();
+    let count = rules.rules.len();
+    let pack = r#"{ "rules": [
+      { "id": "out_of_memory", "title": "OOM", "description": "custom", "fix": "custom fix", "patterns": ["OutOfMemoryError"] },
+      { "id": "optifine", "title": "OptiFine", "description": "OptiFine $version", "fix": "Remove it", "patterns": ["OptiFine (?P<version>\\S+)"] }
+    ] }"#;
+    rules.merge(RuleSet::from_json(pack).unwrap());
+    assert_eq!(rules.rules.len(), count + 1);
+
+    let diagnoses = rules.analyze(&[("game_logs", "OptiFine HD_U_I6 loaded\njava.lang.OutOfMemoryError")]);
+    assert_eq!(diagnoses[0].description, "custom");
+    assert_eq!(diagnoses[1].description, "OptiFine HD_U_I6");
+  }
+
+  #[test]
+  fn rejects_invalid_patterns() {
+    let json = r#"{ "rules": [{ "id": "broken", "title": "", "description": "", "fix": "", "patterns": ["("] }] }"#;
+    assert!(matches!(RuleSet::from_json(json), Err(DiagnosticsError::Pattern { .. })));
+  }
+}
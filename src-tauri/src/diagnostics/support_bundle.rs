@@ -0,0 +1,211 @@
+use std::{ fs::{ self, File }, io::{ self, Read, Write }, path::{ Path, PathBuf } };
+
+use chrono::Local;
+use log::{ info, warn };
+use serde_json::Value;
+use sha1::{ Digest, Sha1 };
+use sysinfo::System;
+use zip::{ write::FileOptions, ZipWriter };
+
+use crate::{
+  app::error::StdError,
+  config::LauncherConfig,
+  constants::{ LAUNCHER_NAME, LAUNCHER_VERSION },
+  launch::session_log::{ list_sessions, sessions_dir },
+  modpack_downloader::ModpackInfo,
+};
+
+const REDACTED: &str = "<redacted>";
+/// Crash reports are never deleted by the game, so only the newest ones are included
+const MAX_CRASH_REPORTS: usize = 5;
+
+/// Everything that goes in the bundle besides the files read from `mc_dir`
+pub struct BundleInfo {
+  pub config: LauncherConfig,
+  pub modpack_info: Option<ModpackInfo>,
+}
+
+/// Writes a zip with the logs, crash reports and system details needed to answer a support ticket
+pub fn create_support_bundle(mc_dir: &Path, target_dir: &Path, info: BundleInfo) -> Result<PathBuf, StdError> {
+  let path = target_dir.join(format!("{}-support-{}.zip", LAUNCHER_NAME.to_lowercase(), Local::now().format("%Y-%m-%d_%H-%M-%S")));
+  let mut zip = ZipWriter::new(File::create(&path)?);
+  let options = FileOptions::default();
+
+  let add_file = |zip: &mut ZipWriter<File>, name: &str, file: &Path| -> Result<(), StdError> {
+    let mut bytes = vec![];
+    if let Err(err) = File::open(file).and_then(|mut f| f.read_to_end(&mut bytes)) {
+      warn!("Skipping {} in support bundle: {}", file.display(), err);
+      return Ok(());
+    }
+    zip.start_file(name, options)?;
+    zip.write_all(&bytes)?;
+    Ok(())
+  };
+
+  for file in list_files(&mc_dir.join("logs").join("gelcorp-launcher"), |_| true) {
+    add_file(&mut zip, &format!("launcher-logs/{}", file_name(&file)), &file)?;
+  }
+  let sessions_dir = sessions_dir(mc_dir);
+  if let Some(session) = list_sessions(&sessions_dir)?.into_iter().next() {
+    add_file(&mut zip, &format!("game-session/{}", session.name), &sessions_dir.join(&session.name))?;
+  }
+
+  let mut crash_reports = list_files(&mc_dir.join("crash-reports"), |name| name.ends_with(".txt"));
+  crash_reports.extend(list_files(mc_dir, |name| name.starts_with("hs_err_pid") && name.ends_with(".log")));
+  crash_reports.sort_by_key(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok());
+  for file in crash_reports.iter().rev().take(MAX_CRASH_REPORTS) {
+    add_file(&mut zip, &format!("crash-reports/{}", file_name(file)), file)?;
+  }
+
+  zip.start_file("launcher_config.json", options)?;
+  zip.write_all(serde_json::to_string_pretty(&redact(serde_json::to_value(&info.config)?))?.as_bytes())?;
+  if let Some(modpack_info) = &info.modpack_info {
+    zip.start_file("modpack_info.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(modpack_info)?.as_bytes())?;
+  }
+  zip.start_file("mods.txt", options)?;
+  zip.write_all(mod_list(&mc_dir.join("mods")).as_bytes())?;
+  zip.start_file("system.txt", options)?;
+  zip.write_all(system_info(mc_dir).as_bytes())?;
+
+  zip.finish()?;
+  info!("Support bundle written to {}", path.display());
+  Ok(path)
+}
+
+/// Replaces every value whose key looks like a credential
+pub fn redact(value: Value) -> Value {
+  match value {
+    Value::Object(map) =>
+      Value::Object(
+        map
+          .into_iter()
+          .map(|(key, value)| {
+            let lower = key.to_lowercase();
+            if lower.contains("token") || lower.contains("secret") || lower.contains("password") {
+              (key, Value::String(REDACTED.to_string()))
+            } else {
+              (key, redact(value))
+            }
+          })
+          .collect()
+      ),
+    Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+    value => value,
+  }
+}
+
+fn file_name(path: &Path) -> String {
+  path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+fn list_files(dir: &Path, filter: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return vec![];
+  };
+  let mut files: Vec<PathBuf> = entries
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+    .filter(|entry| filter(&entry.file_name().to_string_lossy()))
+    .map(|entry| entry.path())
+    .collect();
+  files.sort();
+  files
+}
+
+fn walk_files(dir: &Path, max_depth: usize, files: &mut Vec<PathBuf>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.filter_map(|entry| entry.ok()) {
+    let path = entry.path();
+    if path.is_dir() && max_depth > 0 {
+      walk_files(&path, max_depth - 1, files);
+    } else if path.is_file() {
+      files.push(path);
+    }
+  }
+}
+
+/// `sha1  size  path` of every jar in the mods folder, including optional mods and libs
+fn mod_list(mods_dir: &Path) -> String {
+  let mut jars = vec![];
+  walk_files(mods_dir, 3, &mut jars);
+  jars.retain(|path| path.extension().is_some_and(|ext| ext == "jar"));
+  jars.sort();
+
+  let mut list = String::new();
+  for jar in jars {
+    let relative = jar.strip_prefix(mods_dir).unwrap_or(&jar).display().to_string();
+    match hash_file(&jar) {
+      Ok((hash, size)) => list.push_str(&format!("{hash}  {size:>10}  {relative}\n")),
+      Err(err) => list.push_str(&format!("{:<40}  {:>10}  {relative} ({err})\n", "?", "?")),
+    }
+  }
+  list
+}
+
+fn hash_file(path: &Path) -> io::Result<(String, u64)> {
+  let mut hasher = Sha1::new();
+  let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+  Ok((hex::encode(hasher.finalize()), size))
+}
+
+fn system_info(mc_dir: &Path) -> String {
+  let system = System::new_all();
+  let cpu = system
+    .cpus()
+    .first()
+    .map(|cpu| cpu.brand().trim().to_string())
+    .unwrap_or_default();
+
+  let mut info = format!(
+    "Launcher: {LAUNCHER_NAME} {LAUNCHER_VERSION}\nOS: {} {} ({})\nKernel: {}\nCPU: {} ({} threads)\nMemory: {} MB total, {} MB available\n",
+    System::name().unwrap_or_default(),
+    System::os_version().unwrap_or_default(),
+    System::cpu_arch(),
+    System::kernel_version().unwrap_or_default(),
+    cpu,
+    system.cpus().len(),
+    system.total_memory() / 1024 / 1024,
+    system.available_memory() / 1024 / 1024
+  );
+
+  // Every runtime has a `release` file with its version, like `JAVA_VERSION="17.0.8"`
+  let mut runtime_files = vec![];
+  walk_files(&mc_dir.join("runtimes"), 3, &mut runtime_files);
+  for release in runtime_files.iter().filter(|path| file_name(path) == "release") {
+    let version = fs
+      ::read_to_string(release)
+      .ok()
+      .and_then(|text| text.lines().find_map(|line| line.strip_prefix("JAVA_VERSION=").map(|v| v.trim_matches('"').to_string())));
+    if let Some(version) = version {
+      let runtime = release.parent().unwrap_or(release.as_path());
+      let runtime = runtime.strip_prefix(mc_dir).unwrap_or(runtime);
+      info.push_str(&format!("Java: {version} ({})\n", runtime.display()));
+    }
+  }
+  info
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn redacts_credentials() {
+    let config = json!({
+      "authentication": { "username": "Steve", "moj_token": "abc", "msa_refresh_token": "def" },
+      "accounts": [{ "access_token": "ghi" }],
+      "memory_max": 2048
+    });
+    let redacted = redact(config);
+    assert_eq!(redacted["authentication"]["username"], "Steve");
+    assert_eq!(redacted["authentication"]["moj_token"], REDACTED);
+    assert_eq!(redacted["authentication"]["msa_refresh_token"], REDACTED);
+    assert_eq!(redacted["accounts"][0]["access_token"], REDACTED);
+    assert_eq!(redacted["memory_max"], 2048);
+  }
+}
@@ -1,13 +1,25 @@
-use std::{ fs::{ self, create_dir_all }, path::{ Path, PathBuf } };
+use std::{ fs::{ self, create_dir_all }, path::{ Path, PathBuf }, time::Duration };
 
 use forge_downloader::{ download_utils::forge::ForgeVersionHandler, forge_client_install::ForgeClientInstall };
-use log::info;
+use log::{ info, warn };
 use regex::Regex;
 use reqwest::Client;
+use sha1::{ Digest, Sha1 };
+use tokio_util::sync::CancellationToken;
 
 use crate::app::error::LauncherError;
 
-pub async fn check_forge(mc_dir: &PathBuf, mc_version: &str, forge_version: &str, java_path: &Path) -> Result<(PathBuf, String), LauncherError> {
+const CHECKSUM_TIMEOUT: Duration = Duration::from_secs(30);
+const INSTALLER_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub async fn check_forge(
+  client: &Client,
+  mc_dir: &PathBuf,
+  mc_version: &str,
+  forge_version: &str,
+  java_path: &Path,
+  cancel_token: &CancellationToken
+) -> Result<(PathBuf, String), LauncherError> {
   let versions_dir = mc_dir.join("versions");
 
   // Fetch version
@@ -15,7 +27,10 @@ pub async fn check_forge(mc_dir: &PathBuf, mc_version: &str, forge_version: &str
   let version_info = version_handler.get_by_forge_version(forge_version).expect("Failed to get forge version");
   let installer_path = version_info.get_artifact().get_local_path(&mc_dir.join("libraries"));
 
-  if versions_dir.is_dir() && installer_path.is_file() {
+  // The installer is also used at runtime by forgewrapper, so it's always checked before being trusted
+  ensure_installer(client, &version_info.get_installer_url().to_string(), &installer_path, cancel_token).await?;
+
+  if versions_dir.is_dir() {
     let forge_folder_re = Regex::new(&format!("{}.+{}", mc_version, forge_version)).unwrap();
     let forge_version_name = versions_dir
       .read_dir()?
@@ -27,16 +42,6 @@ pub async fn check_forge(mc_dir: &PathBuf, mc_version: &str, forge_version: &str
     }
   }
 
-  // Download installer if needed
-  if !installer_path.is_file() {
-    if let Some(parent) = installer_path.parent() {
-      create_dir_all(parent)?;
-    }
-
-    let bytes = Client::new().get(version_info.get_installer_url()).send().await?.error_for_status()?.bytes().await?;
-    fs::write(&installer_path, &bytes)?;
-  }
-
   // Open installer
   let mut install_handler = ForgeClientInstall::new(installer_path.clone(), java_path.to_path_buf())?;
   let forge_version_id = install_handler.get_profile().get_version_id();
@@ -44,8 +49,162 @@ pub async fn check_forge(mc_dir: &PathBuf, mc_version: &str, forge_version: &str
   let forge_version_path = mc_dir.join(format!("versions/{id}/{id}.json", id = forge_version_id));
   if !forge_version_path.is_file() {
     info!("Forge not installed! Setting up forge...");
-    install_handler.install_forge(mc_dir, |_| true).await?;
+    tokio::select! {
+      result = install_handler.install_forge(mc_dir, |_| true) => result?,
+      _ = cancel_token.cancelled() => {
+        // Don't leave a half installed version behind, it would be picked up by the next launch
+        let _ = fs::remove_dir_all(versions_dir.join(&forge_version_id));
+        return Err(LauncherError::Cancelled);
+      }
+    }
     info!("Forge installed!");
   }
   Ok((installer_path, forge_version_id))
 }
+
+/// Makes sure the installer at `installer_path` matches the sha1 published next to `installer_url`.
+/// Missing or corrupted installers are downloaded again, and nothing is written to disk unless it matches.
+/// Once verified, the hash is recorded next to the installer so later launches (even offline ones) don't fetch it again.
+async fn ensure_installer(client: &Client, installer_url: &str, installer_path: &Path, cancel_token: &CancellationToken) -> Result<(), LauncherError> {
+  let verified_sha1_path = verified_sha1_path(installer_path);
+  let local_sha1 = installer_path.is_file().then(|| fs::read(installer_path)).transpose()?.map(|bytes| hex::encode(Sha1::digest(bytes)));
+  let verified = fs::read_to_string(&verified_sha1_path).ok();
+  if matches!((&local_sha1, &verified), (Some(local), Some(verified)) if local == verified.trim()) {
+    return Ok(());
+  }
+
+  let expected_sha1 = tokio::select! {
+    sha1 = fetch_installer_sha1(client, installer_url) => sha1?,
+    _ = cancel_token.cancelled() => return Err(LauncherError::Cancelled),
+  };
+
+  if let Some(local_sha1) = local_sha1 {
+    if local_sha1 == expected_sha1 {
+      fs::write(&verified_sha1_path, &expected_sha1)?;
+      return Ok(());
+    }
+    warn!("Forge installer is corrupted (expected {expected_sha1}, found {local_sha1}). Downloading it again...");
+    fs::remove_file(installer_path)?;
+  }
+
+  info!("Downloading forge installer from {installer_url}");
+  let download = async { client.get(installer_url).timeout(INSTALLER_TIMEOUT).send().await?.error_for_status()?.bytes().await };
+  let bytes = tokio::select! {
+    bytes = download => bytes?,
+    _ = cancel_token.cancelled() => return Err(LauncherError::Cancelled),
+  };
+  let downloaded_sha1 = hex::encode(Sha1::digest(&bytes));
+  if downloaded_sha1 != expected_sha1 {
+    return Err(LauncherError::Other(format!("Forge installer checksum mismatch (expected {expected_sha1}, got {downloaded_sha1})")));
+  }
+
+  if let Some(parent) = installer_path.parent() {
+    create_dir_all(parent)?;
+  }
+  fs::write(installer_path, &bytes)?;
+  fs::write(&verified_sha1_path, &expected_sha1)?;
+  Ok(())
+}
+
+fn verified_sha1_path(installer_path: &Path) -> PathBuf {
+  let mut path = installer_path.as_os_str().to_owned();
+  path.push(".sha1");
+  PathBuf::from(path)
+}
+
+async fn fetch_installer_sha1(client: &Client, installer_url: &str) -> Result<String, LauncherError> {
+  let body = client
+    .get(format!("{installer_url}.sha1"))
+    .timeout(CHECKSUM_TIMEOUT)
+    .send().await?
+    .error_for_status()?
+    .text().await?;
+  let sha1 = body.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+  if sha1.len() != 40 || !sha1.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(LauncherError::Other(format!("Invalid forge installer checksum: {}", body.trim())));
+  }
+  Ok(sha1)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env::temp_dir;
+
+  use super::*;
+
+  const INSTALLER: &[u8] = b"forge installer contents";
+
+  fn installer_sha1() -> String {
+    hex::encode(Sha1::digest(INSTALLER))
+  }
+
+  fn installer_path(test_name: &str) -> PathBuf {
+    let dir = temp_dir().join(format!("forge-installer-{test_name}"));
+    let _ = fs::remove_dir_all(&dir);
+    dir.join("forge-installer.jar")
+  }
+
+  #[tokio::test]
+  async fn rejects_tampered_installer() {
+    let mut server = mockito::Server::new_async().await;
+    server.mock("GET", "/forge-installer.jar.sha1").with_body(installer_sha1()).create_async().await;
+    server.mock("GET", "/forge-installer.jar").with_body(b"tampered contents").create_async().await;
+
+    let path = installer_path("tampered");
+    let url = format!("{}/forge-installer.jar", server.url());
+    let result = ensure_installer(&Client::new(), &url, &path, &CancellationToken::new()).await;
+
+    assert!(result.is_err());
+    assert!(!path.exists());
+  }
+
+  #[tokio::test]
+  async fn replaces_corrupted_cached_installer() {
+    let mut server = mockito::Server::new_async().await;
+    server.mock("GET", "/forge-installer.jar.sha1").with_body(installer_sha1()).create_async().await;
+    let download = server.mock("GET", "/forge-installer.jar").with_body(INSTALLER).expect(1).create_async().await;
+
+    let path = installer_path("corrupted");
+    create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, b"truncated").unwrap();
+
+    let url = format!("{}/forge-installer.jar", server.url());
+    ensure_installer(&Client::new(), &url, &path, &CancellationToken::new()).await.unwrap();
+
+    download.assert_async().await;
+    assert_eq!(fs::read(&path).unwrap(), INSTALLER);
+  }
+
+  #[tokio::test]
+  async fn keeps_valid_cached_installer() {
+    let mut server = mockito::Server::new_async().await;
+    server.mock("GET", "/forge-installer.jar.sha1").with_body(format!("{}  forge-installer.jar\n", installer_sha1())).create_async().await;
+    let download = server.mock("GET", "/forge-installer.jar").expect(0).create_async().await;
+
+    let path = installer_path("valid");
+    create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, INSTALLER).unwrap();
+
+    let url = format!("{}/forge-installer.jar", server.url());
+    ensure_installer(&Client::new(), &url, &path, &CancellationToken::new()).await.unwrap();
+
+    download.assert_async().await;
+  }
+
+  #[tokio::test]
+  async fn skips_the_checksum_fetch_for_a_verified_installer() {
+    let mut server = mockito::Server::new_async().await;
+    let checksum = server.mock("GET", "/forge-installer.jar.sha1").with_body(installer_sha1()).expect(1).create_async().await;
+
+    let path = installer_path("verified");
+    create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, INSTALLER).unwrap();
+
+    let url = format!("{}/forge-installer.jar", server.url());
+    ensure_installer(&Client::new(), &url, &path, &CancellationToken::new()).await.unwrap();
+    // The second check must work without the network
+    ensure_installer(&Client::new(), "http://127.0.0.1:9/forge-installer.jar", &path, &CancellationToken::new()).await.unwrap();
+
+    checksum.assert_async().await;
+  }
+}
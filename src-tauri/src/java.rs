@@ -10,8 +10,11 @@ use std::{
 
 use minecraft_launcher_core::version_manager::downloader::progress::ProgressReporter;
 use reqwest::ClientBuilder;
+use tokio_util::sync::CancellationToken;
 use zip::ZipArchive;
 
+use crate::app::error::LauncherError;
+
 pub fn check_java_dir(java_dir: &Path) -> bool {
   let java = java_dir.join("bin").join("java.exe");
   if !java.is_file() {
@@ -26,7 +29,12 @@ pub fn check_java_dir(java_dir: &Path) -> bool {
     .is_ok_and(|c| c.success())
 }
 
-pub async fn download_java(reporter: ProgressReporter, java_dir: &PathBuf, java_version: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn download_java(
+  reporter: ProgressReporter,
+  java_dir: &PathBuf,
+  java_version: &str,
+  cancel_token: &CancellationToken
+) -> Result<(), Box<dyn std::error::Error>> {
   let client = ClientBuilder::new().connect_timeout(Duration::from_secs(30)).build()?;
   let os = match OS {
     "macos" => "mac",
@@ -53,7 +61,20 @@ pub async fn download_java(reporter: ProgressReporter, java_dir: &PathBuf, java_
     reporter.setup(&status, total);
 
     let mut writer = BufWriter::new(&mut file);
-    while let Some(chunk) = response.chunk().await? {
+    loop {
+      let chunk = tokio::select! {
+        chunk = response.chunk() => chunk?,
+        _ = cancel_token.cancelled() => {
+          drop(writer);
+          drop(file);
+          let _ = fs::remove_file(&temp_file_path);
+          reporter.done();
+          return Err(LauncherError::Cancelled.into());
+        }
+      };
+      let Some(chunk) = chunk else {
+        break;
+      };
       current += chunk.len();
       writer.write_all(&chunk)?;
       reporter.progress(current);
@@ -71,6 +92,13 @@ pub async fn download_java(reporter: ProgressReporter, java_dir: &PathBuf, java_
 
     reporter.setup("Extracting java", Some(total));
     for i in 0..total {
+      if cancel_token.is_cancelled() {
+        // A partially extracted runtime could still pass `check_java_dir`
+        drop(archive);
+        let _ = fs::remove_dir_all(java_dir);
+        reporter.done();
+        return Err(LauncherError::Cancelled.into());
+      }
       let mut zip_archive = archive.by_index(i)?;
       if let Some((_, file_name)) = zip_archive.name().split_once('/') {
         progress += 1;
@@ -114,7 +142,7 @@ mod tests {
       println!("Java already exists");
       return Ok(());
     }
-    download_java(Arc::new(EmptyReporter), &java_dir, "17").await?;
+    download_java(Arc::new(EmptyReporter), &java_dir, "17", &CancellationToken::new()).await?;
     assert!(check_java_dir(&java_dir));
     Ok(())
   }
@@ -0,0 +1,228 @@
+use std::{ fs, path::{ Path, PathBuf }, time::SystemTime };
+
+use log::{ info, warn };
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// Mod ids that show up in every stack trace, so they're never suspects
+const IGNORED_MOD_IDS: [&str; 3] = ["minecraft", "forge", "mixin"];
+const MAX_EXCERPT_LINES: usize = 12;
+
+static TRANSFORMER_MOD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"TRANSFORMER/([a-z0-9_.-]+)@").unwrap());
+static SUSPECTED_MOD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^Suspected Mods?: (.+)$").unwrap());
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum CrashKind {
+  /// Minecraft wrote a crash report in `crash-reports/`
+  Game,
+  /// The JVM itself crashed and wrote a `hs_err_pid*.log`
+  Jvm,
+  /// The process exited with an error, but left no report behind
+  Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashSummary {
+  pub exit_code: i32,
+  pub kind: CrashKind,
+  pub description: Option<String>,
+  pub time: Option<String>,
+  pub suspected_mods: Vec<String>,
+  /// First lines of the exception
+  pub excerpt: Vec<String>,
+  pub report_path: Option<PathBuf>,
+  pub other_reports: Vec<PathBuf>,
+}
+
+impl CrashSummary {
+  pub fn headline(&self) -> String {
+    match (&self.kind, &self.description) {
+      (_, Some(description)) => description.clone(),
+      (CrashKind::Jvm, None) => "The java runtime crashed".to_string(),
+      _ => format!("Process exited with code {}", self.exit_code),
+    }
+  }
+}
+
+/// Finds the crash reports written since the watcher was created
+pub struct CrashReportWatcher {
+  game_dir: PathBuf,
+  started: SystemTime,
+}
+
+impl CrashReportWatcher {
+  pub fn new(game_dir: &Path) -> Self {
+    Self { game_dir: game_dir.to_path_buf(), started: SystemTime::now() }
+  }
+
+  pub fn new_reports(&self) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let is_new = |path: &PathBuf| {
+      fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|modified| modified >= self.started)
+    };
+    let list = |dir: &Path, filter: &dyn Fn(&str) -> bool| -> Vec<PathBuf> {
+      let mut files: Vec<PathBuf> = dir
+        .read_dir()
+        .map(|entries| {
+          entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| filter(entry.file_name().to_string_lossy().as_ref()))
+            .map(|entry| entry.path())
+            .filter(is_new)
+            .collect()
+        })
+        .unwrap_or_default();
+      files.sort();
+      files
+    };
+
+    let crash_reports = list(&self.game_dir.join("crash-reports"), &|name| name.ends_with(".txt"));
+    let jvm_reports = list(&self.game_dir, &|name| name.starts_with("hs_err_pid") && name.ends_with(".log"));
+    (crash_reports, jvm_reports)
+  }
+
+  pub fn summarize(&self, exit_code: i32) -> CrashSummary {
+    let (crash_reports, jvm_reports) = self.new_reports();
+    let mut summary = CrashSummary {
+      exit_code,
+      kind: CrashKind::Unknown,
+      description: None,
+      time: None,
+      suspected_mods: vec![],
+      excerpt: vec![],
+      report_path: None,
+      other_reports: vec![],
+    };
+
+    let mut reports = crash_reports.into_iter().map(|path| (CrashKind::Game, path)).chain(jvm_reports.into_iter().map(|path| (CrashKind::Jvm, path)));
+    if let Some((kind, path)) = reports.next() {
+      match fs::read_to_string(&path) {
+        Ok(text) if kind == CrashKind::Game => parse_crash_report(&text, &mut summary),
+        Ok(text) => parse_jvm_report(&text, &mut summary),
+        Err(err) => warn!("Failed to read crash report {}: {}", path.display(), err),
+      }
+      info!("Found crash report: {}", path.display());
+      summary.kind = kind;
+      summary.report_path = Some(path);
+    }
+    summary.other_reports = reports.map(|(_, path)| path).collect();
+    summary
+  }
+}
+
+pub fn parse_crash_report(text: &str, summary: &mut CrashSummary) {
+  let mut lines = text.lines().peekable();
+  while let Some(line) = lines.next() {
+    if let Some(time) = line.strip_prefix("Time: ") {
+      summary.time.get_or_insert(time.trim().to_string());
+    } else if let Some(description) = line.strip_prefix("Description: ") {
+      summary.description.get_or_insert(description.trim().to_string());
+      // The exception follows the description after an empty line
+      while lines.peek().is_some_and(|line| line.trim().is_empty()) {
+        lines.next();
+      }
+      while let Some(line) = lines.next_if(|line| !line.trim().is_empty()) {
+        if summary.excerpt.len() < MAX_EXCERPT_LINES {
+          summary.excerpt.push(line.trim_end().to_string());
+        }
+        collect_transformer_mods(line, &mut summary.suspected_mods);
+      }
+    } else if let Some(captures) = SUSPECTED_MOD_RE.captures(line.trim()) {
+      let suspected = &captures[1];
+      if !suspected.eq_ignore_ascii_case("none") {
+        push_unique(&mut summary.suspected_mods, suspected.trim());
+      }
+    }
+  }
+}
+
+pub fn parse_jvm_report(text: &str, summary: &mut CrashSummary) {
+  let mut lines = text.lines();
+  let mut description = None;
+  while let Some(line) = lines.next() {
+    let line = line.trim_start_matches('#').trim();
+    if description.is_none() && (line.starts_with("EXCEPTION_") || line.starts_with("SIG")) {
+      description = Some(line.to_string());
+    } else if line == "Problematic frame:" {
+      if let Some(frame) = lines.next() {
+        summary.excerpt.push(frame.trim_start_matches('#').trim().to_string());
+      }
+    } else if line.starts_with("Out of Memory Error") || line.starts_with("There is insufficient memory") {
+      description = Some(line.to_string());
+    }
+  }
+  summary.description = description.or_else(|| Some("The java runtime crashed".to_string()));
+}
+
+fn collect_transformer_mods(line: &str, mods: &mut Vec<String>) {
+  for captures in TRANSFORMER_MOD_RE.captures_iter(line) {
+    let mod_id = &captures[1];
+    if !IGNORED_MOD_IDS.contains(&mod_id) {
+      push_unique(mods, mod_id);
+    }
+  }
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) {
+  if !list.iter().any(|item| item == value) {
+    list.push(value.to_string());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CRASH_REPORT: &str = "\
+---- Minecraft Crash Report ----
+// Ouch. That hurt :(
+
+Time: 2024-05-01 18:23:45
+Description: Ticking entity
+
+java.lang.NullPointerException: Cannot invoke \"net.minecraft.world.entity.Entity.m_20185_()\" because \"p_19001_\" is null
+\tat TRANSFORMER/create@0.5.1.f/com.simibubi.create.content.Foo.tick(Foo.java:42) ~[create-1.20.1-0.5.1.f.jar%23190!/:0.5.1.f] {re:classloading}
+\tat TRANSFORMER/minecraft@1.20.1/net.minecraft.world.level.Level.m_46653_(Level.java:479) ~[client-1.20.1-20230612.114412-srg.jar%23311!/:?] {re:mixin}
+
+A detailed walkthrough of the error, its code path and all known details is as follows:
+---------------------------------------------------------------------------------------
+
+-- Head --
+Thread: Server thread
+Suspected Mod: Create (create), Version: 0.5.1.f
+";
+
+  fn empty_summary() -> CrashSummary {
+    CrashSummary {
+      exit_code: 1,
+      kind: CrashKind::Game,
+      description: None,
+      time: None,
+      suspected_mods: vec![],
+      excerpt: vec![],
+      report_path: None,
+      other_reports: vec![],
+    }
+  }
+
+  #[test]
+  fn parses_crash_report_header() {
+    let mut summary = empty_summary();
+    parse_crash_report(CRASH_REPORT, &mut summary);
+    assert_eq!(summary.description.as_deref(), Some("Ticking entity"));
+    assert_eq!(summary.time.as_deref(), Some("2024-05-01 18:23:45"));
+    assert_eq!(summary.excerpt.len(), 3);
+    assert_eq!(summary.suspected_mods, vec!["create", "Create (create), Version: 0.5.1.f"]);
+  }
+
+  #[test]
+  fn parses_jvm_report() {
+    let report = "#\n# A fatal error has been detected by the Java Runtime Environment:\n#\n#  EXCEPTION_ACCESS_VIOLATION (0xc0000005) at pc=0x00007ffb, pid=1234, tid=5678\n#\n# Problematic frame:\n# C  [atio6axx.dll+0x1234]\n";
+    let mut summary = empty_summary();
+    parse_jvm_report(report, &mut summary);
+    assert_eq!(summary.description.as_deref(), Some("EXCEPTION_ACCESS_VIOLATION (0xc0000005) at pc=0x00007ffb, pid=1234, tid=5678"));
+    assert_eq!(summary.excerpt, vec!["C  [atio6axx.dll+0x1234]"]);
+  }
+}
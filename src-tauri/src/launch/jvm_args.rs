@@ -0,0 +1,235 @@
+use log::warn;
+use thiserror::Error;
+
+use crate::constants::{ G1GC_JRE_FLAGS, ZGC_JRE_FLAGS };
+
+/// Options that select the garbage collector, only one of them can be enabled
+const GC_SELECTORS: [&str; 6] = ["UseG1GC", "UseZGC", "UseParallelGC", "UseSerialGC", "UseShenandoahGC", "UseConcMarkSweepGC"];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum JvmArgsError {
+  #[error("Unterminated {0} quote in the java arguments")] UnterminatedQuote(char),
+  #[error("Malformed java option '{0}', expected -XX:+Name, -XX:-Name or -XX:Name=value")] MalformedOption(String),
+  #[error("Only one garbage collector can be enabled, found {0} and {1}")] ConflictingGc(String, String),
+  #[error("'{flag}' is a {expected} option, but the selected garbage collector is {selected}")] WrongGcFlag {
+    flag: String,
+    expected: &'static str,
+    selected: String,
+  },
+  #[error("'{0}' can't be used in the java arguments, use the memory settings instead")] MemoryFlag(String),
+}
+
+/// Splits user supplied java arguments like a shell would.
+///
+/// Whitespace separates arguments, unless it's quoted (`"..."` or `'...'`) or escaped with a backslash.
+/// Backslashes only escape quotes and whitespace, so windows paths like `C:\Users\John Doe` can be written as `"C:\Users\John Doe"`.
+pub fn split_jre_flags(input: &str) -> Result<Vec<String>, JvmArgsError> {
+  let mut args = vec![];
+  let mut current: Option<String> = None;
+  let mut quote: Option<char> = None;
+  let mut chars = input.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match (quote, c) {
+      (Some(q), c) if c == q => quote = None,
+      (Some('"'), '\\') if chars.peek() == Some(&'"') => current.get_or_insert_default().push(chars.next().unwrap()),
+      (Some(_), c) => current.get_or_insert_default().push(c),
+      (None, '"' | '\'') => {
+        quote = Some(c);
+        current.get_or_insert_default();
+      }
+      (None, '\\') if chars.peek().is_some_and(|next| next.is_whitespace() || *next == '"' || *next == '\'') => {
+        current.get_or_insert_default().push(chars.next().unwrap());
+      }
+      (None, c) if c.is_whitespace() => args.extend(current.take()),
+      (None, c) => current.get_or_insert_default().push(c),
+    }
+  }
+
+  if let Some(q) = quote {
+    return Err(JvmArgsError::UnterminatedQuote(q));
+  }
+  args.extend(current);
+  Ok(args)
+}
+
+/// Parses and validates the user supplied java arguments
+pub fn parse_jre_flags(input: &str) -> Result<Vec<String>, JvmArgsError> {
+  let args = split_jre_flags(input)?;
+  validate_jre_flags(&args)?;
+  Ok(args)
+}
+
+pub fn validate_jre_flags(args: &[String]) -> Result<(), JvmArgsError> {
+  let mut selected_gc: Option<&str> = None;
+  let mut gc_options = vec![];
+  for arg in args {
+    if arg.starts_with("-Xmx") || arg.starts_with("-Xms") {
+      return Err(JvmArgsError::MemoryFlag(arg.clone()));
+    }
+    let Some(option) = arg.strip_prefix("-XX:") else {
+      continue;
+    };
+    let (name, enabled) = match option.chars().next() {
+      Some('+') => (&option[1..], true),
+      Some('-') => (&option[1..], false),
+      _ => (option.split_once('=').map(|(name, _)| name).ok_or_else(|| JvmArgsError::MalformedOption(arg.clone()))?, true),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+      return Err(JvmArgsError::MalformedOption(arg.clone()));
+    }
+
+    if GC_SELECTORS.contains(&name) {
+      if !enabled {
+        continue;
+      }
+      if let Some(selected) = selected_gc.filter(|selected| *selected != name) {
+        return Err(JvmArgsError::ConflictingGc(selected.to_string(), name.to_string()));
+      }
+      selected_gc = Some(name);
+    } else {
+      gc_options.push((arg, name));
+    }
+  }
+
+  // Collector specific options only make sense with that collector
+  let known_options: Vec<&str> = preset_options(G1GC_JRE_FLAGS).into_iter().chain(preset_options(ZGC_JRE_FLAGS)).collect();
+  for (arg, name) in gc_options {
+    let (expected, expected_gc) = if name.starts_with("G1") {
+      ("G1GC", "UseG1GC")
+    } else if name.starts_with('Z') {
+      ("ZGC", "UseZGC")
+    } else {
+      continue;
+    };
+    if !known_options.contains(&name) {
+      warn!("Unknown {expected} option: {arg}");
+    }
+    if let Some(selected) = selected_gc.filter(|selected| *selected != expected_gc) {
+      return Err(JvmArgsError::WrongGcFlag { flag: arg.clone(), expected, selected: selected.trim_start_matches("Use").to_string() });
+    }
+  }
+  Ok(())
+}
+
+/// Names of the `-XX` options used by a preset
+fn preset_options(preset: &'static str) -> Vec<&'static str> {
+  preset
+    .split_whitespace()
+    .filter_map(|arg| arg.strip_prefix("-XX:"))
+    .map(|option| option.trim_start_matches(['+', '-']))
+    .map(|option| option.split_once('=').map_or(option, |(name, _)| name))
+    .collect()
+}
+
+/// `-Xms`/`-Xmx` values taken out of the java arguments of older configs
+#[derive(Debug, PartialEq)]
+pub struct MemoryFlags {
+  /// The remaining java arguments
+  pub jre_flags: String,
+  /// Initial heap size in megabytes
+  pub min: Option<u32>,
+  /// Maximum heap size in megabytes
+  pub max: Option<u32>,
+}
+
+/// Removes the memory options from `input`. Returns None if there are none (or the arguments can't be split).
+pub fn take_memory_flags(input: &str) -> Option<MemoryFlags> {
+  let args = split_jre_flags(input).ok()?;
+  let (memory_args, other_args): (Vec<String>, Vec<String>) = args.into_iter().partition(|arg| arg.starts_with("-Xmx") || arg.starts_with("-Xms"));
+  if memory_args.is_empty() {
+    return None;
+  }
+
+  let mut flags = MemoryFlags { jre_flags: join_jre_flags(&other_args), min: None, max: None };
+  for arg in memory_args {
+    let size = parse_memory_size(&arg[4..]);
+    if size.is_none() {
+      warn!("Ignoring invalid memory option: {arg}");
+    }
+    if arg.starts_with("-Xmx") {
+      flags.max = size.or(flags.max);
+    } else {
+      flags.min = size.or(flags.min);
+    }
+  }
+  Some(flags)
+}
+
+/// Joins arguments back into a string that `split_jre_flags` splits the same way
+fn join_jre_flags(args: &[String]) -> String {
+  args
+    .iter()
+    .map(|arg| {
+      if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+      } else {
+        arg.clone()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Parses a java memory size (`512m`, `4G`, `1048576`...) into megabytes
+fn parse_memory_size(value: &str) -> Option<u32> {
+  let (digits, multiplier) = match value.chars().last()?.to_ascii_lowercase() {
+    'k' => (&value[..value.len() - 1], 1u64 << 10),
+    'm' => (&value[..value.len() - 1], 1 << 20),
+    'g' => (&value[..value.len() - 1], 1 << 30),
+    't' => (&value[..value.len() - 1], 1 << 40),
+    _ => (value, 1),
+  };
+  let bytes = digits.parse::<u64>().ok()?.checked_mul(multiplier)?;
+  u32::try_from(bytes >> 20).ok().filter(|mb| *mb > 0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+  }
+
+  #[test]
+  fn splits_quoted_arguments() {
+    let flags = r#"-Dpath="C:\Users\John Doe\mods" '-Dname=it is' -Dspace=a\ b -Dquote=\"x\""#;
+    assert_eq!(
+      split_jre_flags(flags).unwrap(),
+      args(&[r"-Dpath=C:\Users\John Doe\mods", "-Dname=it is", "-Dspace=a b", r#"-Dquote="x""#])
+    );
+    assert_eq!(split_jre_flags("  -Xss1M   \"\"  ").unwrap(), args(&["-Xss1M", ""]));
+  }
+
+  #[test]
+  fn rejects_unterminated_quotes() {
+    assert_eq!(split_jre_flags("-Dpath=\"C:\\Games"), Err(JvmArgsError::UnterminatedQuote('"')));
+  }
+
+  #[test]
+  fn accepts_presets() {
+    assert!(parse_jre_flags(G1GC_JRE_FLAGS).is_ok());
+    assert!(parse_jre_flags(ZGC_JRE_FLAGS).is_ok());
+  }
+
+  #[test]
+  fn rejects_invalid_gc_flags() {
+    assert!(matches!(parse_jre_flags("-XX:+UseG1GC -XX:+UseZGC"), Err(JvmArgsError::ConflictingGc(..))));
+    assert!(matches!(parse_jre_flags("-XX:+UseZGC -XX:G1NewSizePercent=20"), Err(JvmArgsError::WrongGcFlag { .. })));
+    assert!(matches!(parse_jre_flags("-XX:G1NewSizePercent"), Err(JvmArgsError::MalformedOption(_))));
+    assert!(matches!(parse_jre_flags("-Xmx4G"), Err(JvmArgsError::MemoryFlag(_))));
+  }
+
+  #[test]
+  fn takes_memory_flags_out() {
+    let flags = take_memory_flags(r#"-Xms512m -XX:+UseG1GC -Xmx4G "-Dpath=C:\Games\My Pack""#).unwrap();
+    assert_eq!(flags.min, Some(512));
+    assert_eq!(flags.max, Some(4096));
+    assert_eq!(split_jre_flags(&flags.jre_flags).unwrap(), args(&["-XX:+UseG1GC", r"-Dpath=C:\Games\My Pack"]));
+    assert!(parse_jre_flags(&flags.jre_flags).is_ok());
+
+    assert_eq!(take_memory_flags("-Xmxlots").map(|flags| flags.max), Some(None));
+    assert_eq!(take_memory_flags(G1GC_JRE_FLAGS), None);
+  }
+}
@@ -0,0 +1,126 @@
+use std::{ env::consts::ARCH, path::Path };
+
+use serde::Serialize;
+use sysinfo::System;
+use thiserror::Error;
+
+const MB: u64 = 1024 * 1024;
+/// Smallest heap the modpack can start with
+pub const MIN_MEMORY_MB: u32 = 512;
+/// Usable heap of a 32-bit JVM, bigger values fail to reserve the address space
+const MAX_32BIT_MEMORY_MB: u32 = 1536;
+const BASE_RECOMMENDED_MB: u32 = 2048;
+const RECOMMENDED_MB_PER_MOD: u32 = 48;
+
+#[derive(Debug, Error)]
+pub enum MemoryError {
+  #[error("The minimum memory ({min} MB) can't be higher than the maximum memory ({max} MB)")] MinAboveMax {
+    min: u32,
+    max: u32,
+  },
+  #[error("At least {min} MB of memory are required, but only {0} MB are allocated", min = MIN_MEMORY_MB)] TooLow(u32),
+  #[error("A 32-bit java runtime can't use more than {max} MB of memory ({0} MB allocated)", max = MAX_32BIT_MEMORY_MB)] Exceeds32Bit(u32),
+  #[error("{max} MB of memory are allocated, but this computer only has {total} MB")] ExceedsTotal {
+    max: u32,
+    total: u32,
+  },
+  #[error("{max} MB of memory are allocated, but only {available} MB are free. Close other programs or lower the allocated memory")] ExceedsAvailable {
+    max: u32,
+    available: u32,
+  },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SystemMemory {
+  pub total_mb: u32,
+  pub available_mb: u32,
+  pub jvm_64bit: bool,
+}
+
+impl SystemMemory {
+  pub fn current() -> Self {
+    let mut system = System::new();
+    system.refresh_memory();
+    Self {
+      total_mb: (system.total_memory() / MB) as u32,
+      available_mb: (system.available_memory() / MB) as u32,
+      // The java runtime is always downloaded for the launcher's architecture
+      jvm_64bit: matches!(ARCH, "x86_64" | "aarch64"),
+    }
+  }
+
+  /// Highest maximum memory that makes sense to allocate
+  pub fn max_allocatable_mb(&self) -> u32 {
+    if self.jvm_64bit { self.total_mb } else { self.total_mb.min(MAX_32BIT_MEMORY_MB) }
+  }
+
+  pub fn validate(&self, memory_min: u32, memory_max: u32) -> Result<(), MemoryError> {
+    if memory_min > memory_max {
+      return Err(MemoryError::MinAboveMax { min: memory_min, max: memory_max });
+    }
+    if memory_max < MIN_MEMORY_MB {
+      return Err(MemoryError::TooLow(memory_max));
+    }
+    if !self.jvm_64bit && memory_max > MAX_32BIT_MEMORY_MB {
+      return Err(MemoryError::Exceeds32Bit(memory_max));
+    }
+    if memory_max > self.total_mb {
+      return Err(MemoryError::ExceedsTotal { max: memory_max, total: self.total_mb });
+    }
+    if memory_max > self.available_mb {
+      return Err(MemoryError::ExceedsAvailable { max: memory_max, available: self.available_mb });
+    }
+    Ok(())
+  }
+
+  /// Recommended maximum memory for a modpack with `mod_count` mods, leaving room for the OS
+  pub fn recommended_mb(&self, mod_count: usize) -> u32 {
+    let wanted = BASE_RECOMMENDED_MB + RECOMMENDED_MB_PER_MOD * (mod_count as u32);
+    let limit = ((self.max_allocatable_mb() as u64 * 6) / 10) as u32;
+    round_to_512(wanted.min(limit)).max(MIN_MEMORY_MB)
+  }
+}
+
+pub fn count_installed_mods(mc_dir: &Path) -> usize {
+  mc_dir
+    .join("mods")
+    .read_dir()
+    .map(|entries| {
+      entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".jar"))
+        .count()
+    })
+    .unwrap_or(0)
+}
+
+fn round_to_512(memory_mb: u32) -> u32 {
+  (memory_mb / 512) * 512
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SYSTEM: SystemMemory = SystemMemory { total_mb: 16384, available_mb: 8192, jvm_64bit: true };
+
+  #[test]
+  fn validates_allocation() {
+    assert!(SYSTEM.validate(1024, 4096).is_ok());
+    assert!(matches!(SYSTEM.validate(4096, 2048), Err(MemoryError::MinAboveMax { .. })));
+    assert!(matches!(SYSTEM.validate(256, 256), Err(MemoryError::TooLow(256))));
+    assert!(matches!(SYSTEM.validate(1024, 20480), Err(MemoryError::ExceedsTotal { .. })));
+    assert!(matches!(SYSTEM.validate(1024, 10240), Err(MemoryError::ExceedsAvailable { .. })));
+
+    let system_32bit = SystemMemory { jvm_64bit: false, ..SYSTEM };
+    assert!(matches!(system_32bit.validate(1024, 2048), Err(MemoryError::Exceeds32Bit(2048))));
+  }
+
+  #[test]
+  fn recommends_memory_by_mod_count() {
+    assert_eq!(SYSTEM.recommended_mb(0), 2048);
+    assert_eq!(SYSTEM.recommended_mb(100), 6656);
+    // Capped to 60% of the total memory
+    assert_eq!(SYSTEM.recommended_mb(500), 9728);
+  }
+}
@@ -0,0 +1,438 @@
+pub mod crash_report;
+pub mod jvm_args;
+pub mod memory;
+pub mod preview;
+pub mod session_log;
+
+use std::{ fs::{ self, create_dir_all }, future::Future, path::PathBuf, process::Stdio, sync::Arc, time::Duration };
+
+use log::{ debug, error, info, warn };
+use minecraft_launcher_core::{
+  bootstrap::{ auth::UserAuthentication, options::{ GameOptions, GameOptionsBuilder, LauncherOptions }, process::GameProcessBuilder, GameBootstrap },
+  java_manager::JavaRuntimeManager,
+  json::MCVersion,
+  version_manager::{ downloader::progress::ProgressReporter, VersionManager },
+};
+use reqwest::Client;
+use tokio::{ io::{ AsyncBufReadExt, AsyncRead, BufReader }, process::Command, sync::Mutex, task::JoinHandle };
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  app::{ error::{ LauncherError, StdError }, game_status::GameStatus, state::LauncherState },
+  config::{ accounts::RefreshTarget, auth::Authentication, LauncherConfig },
+  constants::{ create_launcher_client, LAUNCHER_NAME, LAUNCHER_VERSION },
+  forge,
+  java::{ check_java_dir, download_java },
+  log_flusher::GAME_LOGS,
+  modpack_downloader::{ ModpackDownloader, ModpackInfo },
+};
+
+use self::{
+  crash_report::CrashReportWatcher,
+  jvm_args::{ parse_jre_flags, JvmArgsError },
+  memory::SystemMemory,
+  session_log::{ sessions_dir, SessionLog },
+};
+
+/*
+Launch stages (each one can be called on its own):
+  1. prepare_auth     Refresh the session if it's about to expire and turn it into the game session
+  2. sync_modpack     Download, verify and install the modpack
+  3. resolve_version  Build the game options and resolve the vanilla version manifest
+  4. ensure_runtime   Install the java runtime required by the version
+  5. ensure_loader    Install forge and switch to its version manifest
+  6. download_files   Download libraries, assets and version files
+  7. build_command    Build the final java command line (a dry run stops here)
+  8. spawn            Start the game and wait for it to exit
+
+Cancelling the pipeline token aborts the current stage, or kills the game once it's running.
+*/
+
+/// How long the output of the game is still read after it exits
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct LaunchPipeline {
+  mc_dir: PathBuf,
+  client: Client,
+  reporter: ProgressReporter,
+  cancel_token: CancellationToken,
+}
+
+/// Version being launched. Filled in by `resolve_version` and updated by the following stages.
+pub struct ResolvedVersion {
+  pub minecraft_version: String,
+  pub version: MCVersion,
+  pub game_opts: GameOptions,
+  pub version_manager: VersionManager,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoaderInfo {
+  pub installer_path: PathBuf,
+  pub version_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct JvmSettings {
+  pub memory_min: u32,
+  pub memory_max: u32,
+  pub jre_flags: Vec<String>,
+}
+
+impl TryFrom<&LauncherConfig> for JvmSettings {
+  type Error = JvmArgsError;
+
+  fn try_from(config: &LauncherConfig) -> Result<Self, Self::Error> {
+    Ok(Self {
+      memory_min: config.memory_min,
+      memory_max: config.memory_max,
+      jre_flags: parse_jre_flags(&config.jre_flags)?,
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct LaunchCommand {
+  pub java_path: PathBuf,
+  pub directory: PathBuf,
+  pub arguments: Vec<String>,
+}
+
+impl LaunchPipeline {
+  pub fn new(mc_dir: PathBuf, reporter: ProgressReporter) -> Self {
+    Self { mc_dir, client: create_launcher_client(None), reporter, cancel_token: CancellationToken::new() }
+  }
+
+  pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+    self.cancel_token = cancel_token;
+    self
+  }
+
+  /// Awaits `future` unless the launch gets cancelled first
+  async fn or_cancelled<T>(&self, future: impl Future<Output = T>) -> Result<T, LauncherError> {
+    tokio::select! {
+      output = future => Ok(output),
+      _ = self.cancel_token.cancelled() => Err(LauncherError::Cancelled),
+    }
+  }
+
+  /// Runs every stage using the current launcher state, from the modpack sync to the game exit
+  pub async fn run(&self, state: &LauncherState) -> Result<(), StdError> {
+    let command = self.prepare(state).await?;
+
+    state.game_status.set(GameStatus::Playing);
+    let crash_watcher = CrashReportWatcher::new(&self.mc_dir);
+    let code = self.spawn(command).await?;
+    if self.cancel_token.is_cancelled() {
+      info!("Game process killed");
+      Err(LauncherError::Cancelled.into())
+    } else if code == 0 {
+      info!("Game exited successfully");
+      Ok(())
+    } else {
+      info!("Game exited with code {code}");
+      Err(LauncherError::GameCrashed(Box::new(crash_watcher.summarize(code))).into())
+    }
+  }
+
+  /// Runs every stage except `spawn`, returning the command that would start the game
+  pub async fn prepare(&self, state: &LauncherState) -> Result<LaunchCommand, StdError> {
+    let LauncherState { launcher_config, modpack_downloader, game_status, .. } = state;
+    self.refresh_session(launcher_config).await?;
+    let (authentication, selected_options, jvm_settings) = {
+      let config = launcher_config.lock().await;
+      (config.active_authentication().cloned(), config.selected_options.clone(), JvmSettings::try_from(&*config))
+    };
+    let jvm_settings = jvm_settings?;
+
+    info!("Attempting to launch the game...");
+    let auth = Self::prepare_auth(authentication)?;
+    Self::check_memory(&jvm_settings)?;
+
+    game_status.set(GameStatus::Downloading);
+    let mut downloader = modpack_downloader.lock().await;
+    let ModpackInfo { minecraft_version, forge_version, .. } = self.sync_modpack(&mut downloader, selected_options).await?;
+
+    let mut resolved = self.resolve_version(&minecraft_version, auth).await?;
+    self.ensure_runtime(&mut resolved).await?;
+    let loader = self.ensure_loader(&mut resolved, &forge_version).await?;
+    self.download_files(&mut resolved).await?;
+    self.build_command(resolved, &loader, &jvm_settings).await
+  }
+
+  /// Refreshes the active microsoft session if it expires soon, saving the new tokens
+  pub async fn refresh_session(&self, launcher_config: &Mutex<LauncherConfig>) -> Result<(), LauncherError> {
+    let changed = LauncherConfig::refresh_sessions(launcher_config, &self.client, RefreshTarget::ActiveIfExpiring).await?
+      .into_result()
+      .map_err(|err| LauncherError::Other(format!("Failed to refresh the Microsoft session, try logging in again: {err}")))?;
+    if changed {
+      let config = launcher_config.lock().await;
+      config.save_to_file().map_err(|err| LauncherError::Other(format!("Failed to save the refreshed session: {err}")))?;
+    }
+    Ok(())
+  }
+
+  pub fn prepare_auth(authentication: Option<Authentication>) -> Result<UserAuthentication, StdError> {
+    let auth: UserAuthentication = authentication.ok_or("Not logged in!")?.try_into()?;
+    info!("Logged in as {}", auth.username);
+    Ok(auth)
+  }
+
+  /// Refuses to launch if the allocated memory doesn't fit in this computer
+  pub fn check_memory(JvmSettings { memory_min, memory_max, .. }: &JvmSettings) -> Result<(), LauncherError> {
+    let system = SystemMemory::current();
+    system.validate(*memory_min, *memory_max).map_err(|err| LauncherError::Other(err.to_string()))?;
+    debug!("Allocating {memory_min}-{memory_max} MB ({} MB free of {} MB)", system.available_mb, system.total_mb);
+    Ok(())
+  }
+
+  pub async fn sync_modpack(&self, downloader: &mut ModpackDownloader, selected_options: Vec<String>) -> Result<ModpackInfo, StdError> {
+    debug!("Checking modpack...");
+    downloader.download_and_install(self.reporter.clone(), selected_options, &self.cancel_token).await?;
+    Ok(downloader.get_or_fetch_modpack_info().await?.clone())
+  }
+
+  pub async fn resolve_version(&self, minecraft_version: &str, auth: UserAuthentication) -> Result<ResolvedVersion, StdError> {
+    let natives_dir = self.mc_dir.join("natives");
+    if fs::remove_dir_all(&natives_dir).is_err() {
+      warn!("Couldn't cleanup natives directory");
+    }
+
+    let game_opts = GameOptionsBuilder::default()
+      .game_dir(self.mc_dir.clone())
+      .java_path(PathBuf::new()) // Replaced by ensure_runtime
+      .launcher_options(LauncherOptions::new(LAUNCHER_NAME, LAUNCHER_VERSION))
+      .authentication(auth)
+      .natives_dir(natives_dir)
+      .build()
+      .map_err(|err| LauncherError::Other(format!("Failed to create game options: {err}")))?;
+    let env_features = game_opts.env_features();
+
+    self.reporter.setup("Fetching version manifest", Some(2));
+    let mut version_manager = self.or_cancelled(VersionManager::load(&self.mc_dir, &env_features, Some(self.client.clone()))).await??;
+    let version = MCVersion::new(minecraft_version);
+    let manifest = self.or_cancelled(version_manager.resolve_local_version(&version, true, false)).await??;
+    self.reporter.status("Resolving local version");
+    self.reporter.progress(1);
+    info!("Queuing library & version downloads");
+    if !manifest.applies_to_current_environment(&env_features) {
+      return Err(format!("Version {} is is incompatible with the current environment", minecraft_version).into());
+    }
+    self.reporter.done();
+
+    Ok(ResolvedVersion {
+      minecraft_version: minecraft_version.to_string(),
+      version,
+      game_opts,
+      version_manager,
+    })
+  }
+
+  pub async fn ensure_runtime(&self, resolved: &mut ResolvedVersion) -> Result<(), StdError> {
+    debug!("Checking java runtime...");
+    let runtimes_dir = self.mc_dir.join("runtimes");
+    create_dir_all(&runtimes_dir)?;
+    let runtime_manager = self.or_cancelled(JavaRuntimeManager::load(&runtimes_dir, &self.client)).await??;
+
+    let objects_dir = self.mc_dir.join("assets").join("objects");
+    let manifest = resolved.version_manager.resolve_local_version(&resolved.version, true, false).await?;
+    if let Some(info) = &manifest.java_version {
+      let java_component = &info.component;
+      // TODO: also check platform
+      if !runtime_manager.get_installed_runtimes()?.contains(java_component) {
+        info!("Java runtime not found. Downloading...");
+        let installed = self.or_cancelled(runtime_manager.install_runtime(&objects_dir, java_component, &self.reporter)).await;
+        if !matches!(installed, Ok(Ok(_))) {
+          // A half written runtime would be reported as installed by the next launch
+          let _ = fs::remove_dir_all(runtime_manager.get_runtime_dir(java_component));
+        }
+        installed??;
+        info!("Java downloaded successfully!");
+      }
+      resolved.game_opts.java_path = runtime_manager.get_java_executable(java_component);
+    } else {
+      let runtime_dir = runtime_manager.get_runtime_dir("modpack-runtime");
+      if !check_java_dir(&runtime_dir) {
+        info!("Java runtime not found. Downloading...");
+        download_java(self.reporter.clone(), &runtime_dir, "17", &self.cancel_token).await.map_err(|err| {
+          if LauncherError::is_cancelled(&err) { LauncherError::Cancelled } else { LauncherError::Other(format!("Failed to download java: {}", err)) }
+        })?;
+        info!("Java downloaded successfully!");
+      }
+      resolved.game_opts.java_path = runtime_manager.get_java_executable("modpack-runtime");
+    }
+    Ok(())
+  }
+
+  pub async fn ensure_loader(&self, resolved: &mut ResolvedVersion, forge_version: &str) -> Result<LoaderInfo, StdError> {
+    let (installer_path, version_name) = forge::check_forge(
+      &self.client,
+      &self.mc_dir,
+      &resolved.minecraft_version,
+      forge_version,
+      &resolved.game_opts.java_path,
+      &self.cancel_token
+    ).await?;
+    info!("Forge Version: {}", version_name);
+
+    resolved.version = MCVersion::new(&version_name);
+    resolved.version_manager.refresh().await?;
+    let manifest = resolved.version_manager.resolve_local_version(&resolved.version, true, false).await?;
+    if !manifest.applies_to_current_environment(&resolved.game_opts.env_features()) {
+      return Err(format!("Version {} is is incompatible with the current environment", resolved.version).into());
+    }
+    self.reporter.done();
+    Ok(LoaderInfo { installer_path, version_name })
+  }
+
+  pub async fn download_files(&self, resolved: &mut ResolvedVersion) -> Result<(), StdError> {
+    let manifest = resolved.version_manager.resolve_local_version(&resolved.version, true, false).await?;
+    self.or_cancelled(resolved.version_manager.download_required_files(&manifest, &self.reporter, None, None)).await??;
+    Ok(())
+  }
+
+  pub async fn build_command(&self, resolved: ResolvedVersion, loader: &LoaderInfo, jvm_settings: &JvmSettings) -> Result<LaunchCommand, StdError> {
+    let ResolvedVersion { version, mut game_opts, mut version_manager, .. } = resolved;
+    game_opts.jvm_args.replace(self.jvm_args(loader, jvm_settings));
+
+    let manifest = version_manager.resolve_local_version(&version, true, false).await?;
+    let GameProcessBuilder { arguments, java_path, directory } = GameBootstrap::new(game_opts)
+      .prepare_launch(&manifest)
+      .map_err(|err| LauncherError::Other(format!("Failed to launch the game: {err}")))?;
+
+    Ok(LaunchCommand {
+      java_path: java_path.ok_or("Missing java path")?,
+      directory: directory.ok_or("Missing game directory")?,
+      arguments,
+    })
+  }
+
+  fn jvm_args(&self, loader: &LoaderInfo, JvmSettings { memory_min, memory_max, jre_flags }: &JvmSettings) -> Vec<String> {
+    let mut args = vec![
+      format!("-Xms{memory_min}M"),
+      format!("-Xmx{memory_max}M"),
+      format!("-Dforgewrapper.librariesDir={}", self.mc_dir.join("libraries").display()),
+      format!("-Dforgewrapper.installer={}", loader.installer_path.display()),
+      format!("-Dforgewrapper.minecraft={}", self.mc_dir.join(format!("versions/{0}/{0}.jar", loader.version_name)).display())
+    ];
+    args.extend(jre_flags.iter().cloned());
+    args
+  }
+
+  /// Starts the game and forwards its output to the game logs and the session log. Returns the exit code.
+  pub async fn spawn(&self, command: LaunchCommand) -> Result<i32, StdError> {
+    let LaunchCommand { java_path, directory, arguments } = command;
+    let session_log = match SessionLog::create(&sessions_dir(&self.mc_dir)) {
+      Ok(session_log) => Some(Arc::new(session_log)),
+      Err(err) => {
+        warn!("Failed to create game session log: {}", err);
+        None
+      }
+    };
+    let mut process = Command::new(java_path)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .current_dir(directory)
+      .args(arguments)
+      .spawn()
+      .map_err(|err| LauncherError::Other(format!("Failed to launch the game: {err}")))?;
+    let stdout = BufReader::new(process.stdout.take().unwrap());
+    let stderr = BufReader::new(process.stderr.take().unwrap());
+
+    /// Forwards every line until the pipe closes, which happens after the game exits and its last output was read
+    fn log_lines(mut reader: BufReader<impl AsyncRead + Unpin + Send + 'static>, session_log: Option<Arc<SessionLog>>) -> JoinHandle<()> {
+      tokio::spawn(async move {
+        loop {
+          let mut buf = Vec::new();
+          match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) => break,
+            Ok(_) => {
+              let line = String::from_utf8(buf).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+              if line == "false" {
+                continue; // TODO: find out why this happens
+              }
+
+              println!("{}", line.trim_end());
+              if let Some(session_log) = &session_log {
+                session_log.write_line(line.trim_end());
+              }
+              GAME_LOGS.log(line.trim_end());
+            }
+            Err(err) => {
+              error!("Failed to read game output: {}", err);
+              break;
+            }
+          }
+        }
+      })
+    }
+
+    let stdout_reader = log_lines(stdout, session_log.clone());
+    let stderr_reader = log_lines(stderr, session_log.clone());
+
+    let exit_status = tokio::select! {
+      exit_status = process.wait() => exit_status,
+      _ = self.cancel_token.cancelled() => {
+        info!("Killing the game process...");
+        process.kill().await?;
+        process.wait().await
+      }
+    };
+
+    // The output left in the pipes (usually the crash stack trace) must reach the session log before it's archived.
+    // Processes started by the game can keep the pipes open, so don't wait for them forever.
+    let readers = futures::future::join(stdout_reader, stderr_reader);
+    if tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, readers).await.is_err() {
+      warn!("The game output is still open after the game exited, archiving the session log without it");
+    }
+    if let Some(session_log) = session_log {
+      if let Err(err) = session_log.finish() {
+        warn!("Failed to archive game session log: {}", err);
+      }
+    }
+
+    Ok(exit_status?.code().unwrap_or(-1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use minecraft_launcher_core::version_manager::downloader::progress::CallbackReporter;
+  use uuid::Uuid;
+
+  use super::*;
+
+  #[test]
+  fn prepare_auth_requires_login() {
+    assert!(LaunchPipeline::prepare_auth(None).is_err());
+  }
+
+  #[test]
+  fn prepare_auth_offline() {
+    let authentication = Authentication::Offline { username: "Steve".to_string(), uuid: Uuid::nil() };
+    let auth = LaunchPipeline::prepare_auth(Some(authentication)).unwrap();
+    assert_eq!(auth.username, "Steve");
+  }
+
+  #[test]
+  fn jvm_args_fill_memory_and_forgewrapper_paths() {
+    let mc_dir = PathBuf::from("minecraft");
+    let pipeline = LaunchPipeline::new(mc_dir.clone(), Arc::new(CallbackReporter::new(|_| {})));
+    let loader = LoaderInfo { installer_path: mc_dir.join("libraries/forge-installer.jar"), version_name: "1.20.1-forge-47.2.0".to_string() };
+    let config = LauncherConfig { memory_min: 1024, memory_max: 4096, jre_flags: r#"-XX:+UseG1GC "-Dpath=C:\My Games""#.to_string(), ..Default::default() };
+
+    let args = pipeline.jvm_args(&loader, &JvmSettings::try_from(&config).unwrap());
+    assert_eq!(args, [
+      "-Xms1024M".to_string(),
+      "-Xmx4096M".to_string(),
+      format!("-Dforgewrapper.librariesDir={}", mc_dir.join("libraries").display()),
+      format!("-Dforgewrapper.installer={}", loader.installer_path.display()),
+      format!("-Dforgewrapper.minecraft={}", mc_dir.join("versions/1.20.1-forge-47.2.0/1.20.1-forge-47.2.0.jar").display()),
+      "-XX:+UseG1GC".to_string(),
+      r"-Dpath=C:\My Games".to_string(),
+    ]);
+
+    let config = LauncherConfig { jre_flags: "-Xmx8G".to_string(), ..Default::default() };
+    assert!(matches!(JvmSettings::try_from(&config), Err(JvmArgsError::MemoryFlag(_))));
+  }
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+
+use serde::{ Deserialize, Serialize };
+
+use super::LaunchCommand;
+
+const REDACTED: &str = "<redacted>";
+const SECRET_GAME_ARGS: [&str; 4] = ["--accessToken", "--session", "--clientId", "--xuid"];
+/// JVM options whose value is passed as a separate argument
+const JVM_ARGS_WITH_VALUE: [&str; 10] = [
+  "-cp",
+  "-classpath",
+  "--class-path",
+  "-p",
+  "--module-path",
+  "--add-modules",
+  "--add-opens",
+  "--add-exports",
+  "--add-reads",
+  "--patch-module",
+];
+
+/// Launch command split into its parts, with the session tokens redacted so it can be shared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaunchPreview {
+  pub java_path: PathBuf,
+  pub directory: PathBuf,
+  pub jvm_args: Vec<String>,
+  pub main_class: Option<String>,
+  pub game_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptFormat {
+  Shell,
+  Batch,
+}
+
+impl ScriptFormat {
+  pub fn native() -> Self {
+    if cfg!(windows) { Self::Batch } else { Self::Shell }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Self::Shell => "sh",
+      Self::Batch => "bat",
+    }
+  }
+}
+
+impl From<&LaunchCommand> for LaunchPreview {
+  fn from(LaunchCommand { java_path, directory, arguments }: &LaunchCommand) -> Self {
+    let mut main_class_index = None;
+    let mut skip_value = false;
+    for (i, arg) in arguments.iter().enumerate() {
+      if skip_value {
+        skip_value = false;
+      } else if JVM_ARGS_WITH_VALUE.contains(&arg.as_str()) {
+        skip_value = true;
+      } else if !arg.starts_with('-') {
+        main_class_index = Some(i);
+        break;
+      }
+    }
+
+    let (jvm_args, main_class, game_args) = match main_class_index {
+      Some(i) => (arguments[..i].to_vec(), Some(arguments[i].clone()), arguments[i + 1..].to_vec()),
+      None => (arguments.clone(), None, vec![]),
+    };
+
+    Self {
+      java_path: java_path.clone(),
+      directory: directory.clone(),
+      jvm_args,
+      main_class,
+      game_args: redact_game_args(game_args),
+    }
+  }
+}
+
+impl LaunchPreview {
+  pub fn arguments(&self) -> impl Iterator<Item = &String> {
+    self.jvm_args.iter().chain(&self.main_class).chain(&self.game_args)
+  }
+
+  pub fn to_script(&self, format: ScriptFormat) -> String {
+    let java_path = self.java_path.to_string_lossy();
+    let directory = self.directory.to_string_lossy();
+    match format {
+      ScriptFormat::Shell => {
+        let args: Vec<String> = self.arguments().map(|arg| quote_shell(arg)).collect();
+        format!("#!/bin/sh\ncd {} || exit 1\nexec {} {}\n", quote_shell(&directory), quote_shell(&java_path), args.join(" \\\n  "))
+      }
+      ScriptFormat::Batch => {
+        let args: Vec<String> = self.arguments().map(|arg| quote_batch(arg)).collect();
+        format!("@echo off\r\ncd /d {}\r\n{} {}\r\n", quote_batch(&directory), quote_batch(&java_path), args.join(" ^\r\n  "))
+      }
+    }
+  }
+}
+
+fn redact_game_args(mut game_args: Vec<String>) -> Vec<String> {
+  let mut redact_next = false;
+  for arg in &mut game_args {
+    if redact_next {
+      *arg = REDACTED.to_string();
+    }
+    redact_next = SECRET_GAME_ARGS.contains(&arg.as_str());
+  }
+  game_args
+}
+
+fn quote_shell(arg: &str) -> String {
+  let is_safe = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+  if is_safe { arg.to_string() } else { format!("'{}'", arg.replace('\'', "'\\''")) }
+}
+
+fn quote_batch(arg: &str) -> String {
+  let arg = arg.replace('%', "%%");
+  let needs_quotes = arg.is_empty() || arg.chars().any(|c| c.is_whitespace() || "&|<>^(),;=\"".contains(c));
+  if needs_quotes { format!("\"{}\"", arg.replace('"', "\"\"")) } else { arg }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn command() -> LaunchCommand {
+    let arguments = [
+      "-Xmx2048M",
+      "-cp",
+      "/libs/a.jar:/libs/b.jar",
+      "--add-opens",
+      "java.base/java.lang=ALL-UNNAMED",
+      "io.github.zekerzhayard.forgewrapper.installer.Main",
+      "--username",
+      "Steve",
+      "--accessToken",
+      "secret-token",
+      "--gameDir",
+      "/home/John Doe/.launcher",
+    ];
+    LaunchCommand {
+      java_path: PathBuf::from("/runtimes/java/bin/java"),
+      directory: PathBuf::from("/home/John Doe/.launcher"),
+      arguments: arguments.map(String::from).to_vec(),
+    }
+  }
+
+  #[test]
+  fn splits_arguments_and_redacts_tokens() {
+    let preview = LaunchPreview::from(&command());
+    assert_eq!(preview.jvm_args.len(), 5);
+    assert_eq!(preview.main_class.as_deref(), Some("io.github.zekerzhayard.forgewrapper.installer.Main"));
+    assert_eq!(preview.game_args[3], REDACTED);
+    assert!(!preview.to_script(ScriptFormat::Shell).contains("secret-token"));
+  }
+
+  #[test]
+  fn quotes_paths_with_spaces() {
+    let preview = LaunchPreview::from(&command());
+    assert!(preview.to_script(ScriptFormat::Shell).contains("'/home/John Doe/.launcher'"));
+    assert!(preview.to_script(ScriptFormat::Batch).contains("\"/home/John Doe/.launcher\""));
+  }
+}
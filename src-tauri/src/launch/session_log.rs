@@ -0,0 +1,183 @@
+use std::{ fs::{ self, File }, io::{ self, LineWriter, Read, Write }, path::{ Path, PathBuf }, sync::Mutex, time::SystemTime };
+
+use chrono::{ DateTime, Local };
+use flate2::{ read::GzDecoder, write::GzEncoder, Compression };
+use log::{ info, warn };
+use serde::Serialize;
+
+/// Archived sessions kept on disk, the oldest ones are deleted first
+const MAX_SESSIONS: usize = 20;
+
+pub fn sessions_dir(mc_dir: &Path) -> PathBuf {
+  mc_dir.join("logs").join("game-sessions")
+}
+
+/// Output of a single launch, written as `{date}.log` while the game runs and gzipped when it exits
+pub struct SessionLog {
+  path: PathBuf,
+  writer: Mutex<Option<LineWriter<File>>>,
+}
+
+impl SessionLog {
+  pub fn create(dir: &Path) -> io::Result<Self> {
+    fs::create_dir_all(dir)?;
+    // Sessions left uncompressed by a launcher that didn't exit cleanly
+    for entry in fs::read_dir(dir)?.filter_map(|entry| entry.ok()) {
+      let path = entry.path();
+      if path.extension().is_some_and(|ext| ext == "log") {
+        if let Err(err) = compress(&path) {
+          warn!("Failed to archive game session {}: {}", path.display(), err);
+        }
+      }
+    }
+
+    let name = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let path = {
+      let mut i = 0;
+      loop {
+        let path = dir.join(if i == 0 { format!("{name}.log") } else { format!("{name}-{i}.log") });
+        if !path.exists() && !path.with_extension("log.gz").exists() {
+          break path;
+        }
+        i += 1;
+      }
+    };
+    let file = File::create(&path)?;
+    info!("Writing game output to {}", path.display());
+    Ok(Self { path, writer: Mutex::new(Some(LineWriter::new(file))) })
+  }
+
+  pub fn write_line(&self, line: &str) {
+    if let Some(writer) = self.writer.lock().unwrap().as_mut() {
+      if let Err(err) = writeln!(writer, "{line}") {
+        warn!("Failed to write game session log: {}", err);
+      }
+    }
+  }
+
+  /// Closes and compresses the log, then deletes the sessions over the retention limit
+  pub fn finish(&self) -> io::Result<PathBuf> {
+    if let Some(mut writer) = self.writer.lock().unwrap().take() {
+      writer.flush()?;
+    }
+    let archived = compress(&self.path)?;
+    if let Some(dir) = self.path.parent() {
+      prune_sessions(dir, MAX_SESSIONS)?;
+    }
+    Ok(archived)
+  }
+}
+
+fn compress(path: &Path) -> io::Result<PathBuf> {
+  let target = path.with_extension("log.gz");
+  let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+  io::copy(&mut File::open(path)?, &mut encoder)?;
+  encoder.finish()?;
+  fs::remove_file(path)?;
+  Ok(target)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+  /// File name, used to read the session
+  pub name: String,
+  pub date: DateTime<Local>,
+  pub size: u64,
+  /// Still being written by a running game
+  pub active: bool,
+}
+
+/// Every session in `dir`, newest first
+pub fn list_sessions(dir: &Path) -> io::Result<Vec<SessionInfo>> {
+  if !dir.is_dir() {
+    return Ok(vec![]);
+  }
+  let mut sessions: Vec<SessionInfo> = fs
+    ::read_dir(dir)?
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let active = name.ends_with(".log");
+      if !active && !name.ends_with(".log.gz") {
+        return None;
+      }
+      let meta = entry.metadata().ok()?;
+      let date = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH).into();
+      Some(SessionInfo { name, date, size: meta.len(), active })
+    })
+    .collect();
+  sessions.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| b.name.cmp(&a.name)));
+  Ok(sessions)
+}
+
+/// Reads a session by the name returned by `list_sessions`
+pub fn read_session(dir: &Path, name: &str) -> io::Result<String> {
+  // Only plain file names, so the command can't be used to read anything else
+  if !list_sessions(dir)?.iter().any(|session| session.name == name) {
+    return Err(io::Error::new(io::ErrorKind::NotFound, format!("Unknown game session: {name}")));
+  }
+  let path = dir.join(name);
+  let mut text = String::new();
+  if name.ends_with(".gz") {
+    GzDecoder::new(File::open(path)?).read_to_string(&mut text)?;
+  } else {
+    File::open(path)?.read_to_string(&mut text)?;
+  }
+  Ok(text)
+}
+
+fn prune_sessions(dir: &Path, keep: usize) -> io::Result<()> {
+  let archived = list_sessions(dir)?.into_iter().filter(|session| !session.active);
+  for session in archived.skip(keep) {
+    info!("Deleting old game session {}", session.name);
+    fs::remove_file(dir.join(&session.name))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env::temp_dir;
+
+  use super::*;
+
+  fn test_dir(test_name: &str) -> PathBuf {
+    let dir = temp_dir().join(format!("game-sessions-{test_name}"));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  #[test]
+  fn writes_and_archives_session() {
+    let dir = test_dir("archive");
+    let session = SessionLog::create(&dir).unwrap();
+    session.write_line("[12:00:00] [main/INFO]: Hello");
+    session.write_line("[12:00:01] [main/INFO]: Bye");
+    assert!(list_sessions(&dir).unwrap()[0].active);
+
+    let archived = session.finish().unwrap();
+    let sessions = list_sessions(&dir).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(!sessions[0].active);
+    assert_eq!(dir.join(&sessions[0].name), archived);
+    assert_eq!(read_session(&dir, &sessions[0].name).unwrap(), "[12:00:00] [main/INFO]: Hello\n[12:00:01] [main/INFO]: Bye\n");
+  }
+
+  #[test]
+  fn rejects_unknown_sessions() {
+    let dir = test_dir("unknown");
+    SessionLog::create(&dir).unwrap().finish().unwrap();
+    assert!(read_session(&dir, "../../launcher_config.json").is_err());
+  }
+
+  #[test]
+  fn prunes_old_sessions() {
+    let dir = test_dir("prune");
+    fs::create_dir_all(&dir).unwrap();
+    for i in 0..5 {
+      fs::write(dir.join(format!("2024-01-0{i}_00-00-00.log.gz")), []).unwrap();
+    }
+    prune_sessions(&dir, 3).unwrap();
+    assert_eq!(list_sessions(&dir).unwrap().len(), 3);
+  }
+}
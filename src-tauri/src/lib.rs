@@ -4,22 +4,27 @@
 pub mod app;
 pub mod constants;
 
+mod cli;
 mod config;
 mod logger;
 mod java;
 mod modpack_downloader;
 mod log_flusher;
+mod log_record;
+mod log_query;
 mod forge;
+mod launch;
+mod diagnostics;
 
 use app::{ game_status::GameStatusState, state::LauncherState };
 use config::LauncherConfig;
 use constants::{ LAUNCHER_DIRECTORY, UPDATE_ENDPOINTS };
-use log::info;
+use log::{ error, info };
 use serde::Serialize;
 
 use tokio::sync::Mutex;
 
-use crate::{ log_flusher::LAUNCHER_LOGS, logger::{ setup_logger, LauncherAppender }, modpack_downloader::{ ModpackDownloader, ModpackProvider } };
+use crate::{ log_flusher::LAUNCHER_LOGS, logger::{ apply_logging_config, setup_logger, LauncherAppender }, modpack_downloader::{ ModpackDownloader, ModpackProvider } };
 
 #[derive(Default, Serialize, Clone)]
 pub struct DownloadProgress {
@@ -31,6 +36,11 @@ pub struct DownloadProgress {
 #[tokio::main]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub async fn run() {
+  let headless_args = cli::headless_args();
+  if headless_args.is_some() {
+    cli::attach_console();
+  }
+
   let logs_dir = LAUNCHER_DIRECTORY.join("logs").join("gelcorp-launcher");
   setup_logger(&logs_dir).expect("Failed to initialize logger");
   info!("Starting tauri application...");
@@ -43,6 +53,9 @@ pub async fn run() {
   );
 
   let launcher_config = LauncherConfig::load_from_file().await;
+  if let Err(err) = apply_logging_config(&launcher_config.logging) {
+    error!("Failed to apply logging config: {}", err);
+  }
   let providers: Vec<ModpackProvider> = launcher_config.providers
     .iter()
     .map(|s| ModpackProvider::new(s))
@@ -53,8 +66,15 @@ pub async fn run() {
     launcher_config: Mutex::new(launcher_config),
     modpack_downloader: Mutex::new(modpack_downloader),
     game_status: GameStatusState::new(),
+    last_crash: std::sync::Mutex::new(None),
+    msa_device_login: std::sync::Mutex::new(None),
   };
 
+  if let Some(args) = headless_args {
+    let code = cli::run(launcher_state, args).await;
+    std::process::exit(code);
+  }
+
   let update_endpoints = UPDATE_ENDPOINTS.split(' ')
     .map(|s| s.parse().expect("Failed to parse update endpoint"))
     .collect();
@@ -1,53 +1,159 @@
-use std::{ collections::VecDeque, mem, sync::Mutex, thread::{ self, sleep }, time::Duration };
+use std::{
+  collections::VecDeque,
+  mem,
+  sync::{ atomic::{ AtomicUsize, Ordering }, Condvar, Mutex },
+  thread::{ self, sleep },
+  time::{ Duration, Instant },
+};
 
+use log::warn;
 use serde::de::DeserializeOwned;
 use tauri::{ AppHandle, Emitter, Runtime, plugin::{ Builder, PluginApi, TauriPlugin } };
 
-pub static GAME_LOGS: LogFlusher = LogFlusher::new("game_logs");
-pub static LAUNCHER_LOGS: LogFlusher = LogFlusher::new("launcher_logs");
+use crate::{ config::logging::DEFAULT_BUFFER_SIZE, log_query::{ LogPage, LogQuery }, log_record::{ LogFormat, LogRecord } };
+
+pub static GAME_LOGS: LogFlusher = LogFlusher::new("game_logs", LogFormat::Game);
+pub static LAUNCHER_LOGS: LogFlusher = LogFlusher::new("launcher_logs", LogFormat::Launcher);
 static ALL_LOGS: [&LogFlusher; 2] = [&GAME_LOGS, &LAUNCHER_LOGS];
 
+/// Time to wait after the first new record so a burst of lines is emitted in a single event
+const BATCH_WINDOW: Duration = Duration::from_millis(16);
+/// Upper bound of the extra wait after a slow emit
+const MAX_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Log target of the flusher's own errors, kept out of the launcher logs so a failing emit doesn't feed itself
+pub const LOG_FLUSHER_TARGET: &str = "log_flusher";
+
+/// Set when any flusher has records waiting to be emitted
+static HAS_PENDING: Mutex<bool> = Mutex::new(false);
+static PENDING_CHANGED: Condvar = Condvar::new();
+
+fn notify_pending() {
+  *HAS_PENDING.lock().unwrap() = true;
+  PENDING_CHANGED.notify_one();
+}
+
+fn wait_for_pending() {
+  let mut has_pending = PENDING_CHANGED.wait_while(HAS_PENDING.lock().unwrap(), |has_pending| !*has_pending).unwrap();
+  *has_pending = false;
+}
+
+struct LogBuffer {
+  next_id: u64,
+  /// Records not emitted yet, the last one may also be in `logs` if it's still growing
+  pending: Vec<LogRecord>,
+  logs: VecDeque<LogRecord>,
+}
+
 pub struct LogFlusher {
   id: &'static str,
-  buffer: Mutex<Vec<String>>,
-  logs: Mutex<VecDeque<String>>,
+  format: LogFormat,
+  capacity: AtomicUsize,
+  buffer: Mutex<LogBuffer>,
 }
 
 impl LogFlusher {
-  pub const fn new(id: &'static str) -> Self {
+  pub const fn new(id: &'static str, format: LogFormat) -> Self {
     Self {
       id,
-      buffer: Mutex::new(vec![]),
-      logs: Mutex::new(VecDeque::new()),
+      format,
+      capacity: AtomicUsize::new(DEFAULT_BUFFER_SIZE),
+      buffer: Mutex::new(LogBuffer { next_id: 0, pending: vec![], logs: VecDeque::new() }),
     }
   }
 
-  pub fn get_all(&self) -> Vec<String> {
-    self.logs.lock().unwrap().iter().cloned().collect()
+  /// Changes how many records are kept, dropping the oldest ones if needed
+  pub fn set_capacity(&self, capacity: usize) {
+    self.capacity.store(capacity, Ordering::Relaxed);
+    let mut buffer = self.buffer.lock().unwrap();
+    while buffer.logs.len() > capacity {
+      let _ = buffer.logs.pop_front();
+    }
+  }
+
+  pub fn find(id: &str) -> Option<&'static LogFlusher> {
+    ALL_LOGS.iter().copied().find(|flusher| flusher.id == id)
+  }
+
+  pub fn query(&self, query: &LogQuery) -> Result<LogPage, regex::Error> {
+    query.run(&self.buffer.lock().unwrap().logs)
+  }
+
+  pub fn get_all(&self) -> Vec<LogRecord> {
+    self.buffer.lock().unwrap().logs.iter().cloned().collect()
+  }
+
+  /// Raw text of every stored record, one line per line
+  pub fn get_text(&self) -> String {
+    let buffer = self.buffer.lock().unwrap();
+    buffer.logs
+      .iter()
+      .map(|record| record.raw.as_str())
+      .collect::<Vec<_>>()
+      .join("\n")
   }
 
-  // TODO: avoid cloning
   pub fn log(&self, text: impl AsRef<str>) {
-    let text = text.as_ref().to_string();
-    self.buffer.lock().unwrap().push(text.clone());
+    self.push_lines(text.as_ref());
+    notify_pending();
+  }
 
-    let mut logs = self.logs.lock().unwrap();
-    logs.push_back(text);
-    while logs.len() >= 1001 {
-      let _ = logs.pop_front();
+  fn push_lines(&self, text: &str) {
+    let capacity = self.capacity.load(Ordering::Relaxed);
+    let mut guard = self.buffer.lock().unwrap();
+    let buffer = &mut *guard;
+    for line in text.lines() {
+      let id = buffer.next_id;
+      let record = match LogRecord::parse(id, self.format, line) {
+        Some(record) => record,
+        None => {
+          // Continuation of the previous record, like a stack trace
+          if let Some(last) = buffer.logs.back_mut().filter(|_| LogRecord::is_continuation(line)) {
+            if last.append(line) {
+              let last = last.clone();
+              match buffer.pending.last_mut() {
+                Some(pending) if pending.id == last.id => *pending = last,
+                _ => buffer.pending.push(last),
+              }
+              continue;
+            }
+          }
+          LogRecord::unformatted(id, buffer.logs.back().map(|last| last.level).unwrap_or_default(), line)
+        }
+      };
+      buffer.next_id += 1;
+      buffer.pending.push(record.clone());
+      buffer.logs.push_back(record);
+      while buffer.logs.len() > capacity {
+        let _ = buffer.logs.pop_front();
+      }
+    }
+    // If the webview can't keep up, records that already left the buffer aren't worth sending
+    if buffer.pending.len() > capacity {
+      let overflow = buffer.pending.len() - capacity;
+      buffer.pending.drain(..overflow);
     }
   }
 
   pub fn flush<R: Runtime>(&self, app: &AppHandle<R>) {
-    let mut logs = self.buffer.lock().unwrap();
-    if !logs.is_empty() {
-      app.emit(self.id, mem::take(&mut *logs)).unwrap();
+    let pending = mem::take(&mut self.buffer.lock().unwrap().pending);
+    if pending.is_empty() {
+      return;
+    }
+    if let Err(err) = app.emit(self.id, pending) {
+      warn!(target: LOG_FLUSHER_TARGET, "Failed to emit {}: {}", self.id, err);
     }
   }
 }
 
 //
 
+pub fn set_all_capacities(capacity: usize) {
+  for flusher in &ALL_LOGS {
+    flusher.set_capacity(capacity);
+  }
+}
+
 pub fn flush_all_logs<R: Runtime>(app: &AppHandle<R>) {
   for flusher in &ALL_LOGS {
     flusher.flush(app);
@@ -61,8 +167,12 @@ fn setup_log_flusher<R: Runtime, C: DeserializeOwned>(app: &AppHandle<R>, _api:
     .name("launcher-log-watcher".into())
     .spawn(move || {
       loop {
+        wait_for_pending();
+        sleep(BATCH_WINDOW);
+        let started = Instant::now();
         flush_all_logs(&app);
-        sleep(Duration::from_millis(5));
+        // A slow webview makes emitting slow, so give it some time before sending the next batch
+        sleep(started.elapsed().min(MAX_BACKOFF));
       }
     })
     .expect("Failed to spawn log watcher thread");
@@ -70,15 +180,53 @@ fn setup_log_flusher<R: Runtime, C: DeserializeOwned>(app: &AppHandle<R>, _api:
 }
 
 #[tauri::command]
-fn get_logs(id: &str) -> Result<Vec<String>, String> {
-  for flusher in &ALL_LOGS {
-    if flusher.id == id {
-      return Ok(flusher.get_all());
-    }
-  }
-  Err(format!("Unknown log id: {id}"))
+fn get_logs(id: &str) -> Result<Vec<LogRecord>, String> {
+  LogFlusher::find(id)
+    .map(|flusher| flusher.get_all())
+    .ok_or_else(|| format!("Unknown log id: {id}"))
 }
 
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
   Builder::new("log-flusher").setup(setup_log_flusher).invoke_handler(tauri::generate_handler![get_logs]).build()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn continuation_lines_update_pending_record() {
+    let flusher = LogFlusher::new("test", LogFormat::Game);
+    flusher.log("[12:00:00] [main/ERROR]: Exception caught\njava.lang.NullPointerException");
+    flusher.log("\tat net.minecraft.client.Minecraft.run(Minecraft.java:1)");
+
+    let buffer = flusher.buffer.lock().unwrap();
+    assert_eq!(buffer.pending.len(), 1);
+    assert_eq!(buffer.pending[0].message.lines().count(), 3);
+    assert_eq!(buffer.logs.len(), 1);
+  }
+
+  #[test]
+  fn other_unformatted_lines_start_new_records() {
+    let flusher = LogFlusher::new("test", LogFormat::Game);
+    flusher.log("[12:00:00] [main/INFO]: Loading\nOpenAL initialized.");
+
+    let buffer = flusher.buffer.lock().unwrap();
+    assert_eq!(buffer.logs.len(), 2);
+    assert_eq!(buffer.logs[1].message, "OpenAL initialized.");
+  }
+
+  #[test]
+  fn pending_records_are_bounded() {
+    let flusher = LogFlusher::new("test", LogFormat::Launcher);
+    flusher.set_capacity(10);
+    for i in 0..25 {
+      flusher.log(format!("[12:00:00 INFO]: Line {i}"));
+    }
+
+    let buffer = flusher.buffer.lock().unwrap();
+    assert_eq!(buffer.logs.len(), 10);
+    assert_eq!(buffer.pending.len(), 10);
+    assert_eq!(buffer.pending[0].message, "Line 15");
+  }
+}
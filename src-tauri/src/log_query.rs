@@ -0,0 +1,133 @@
+use regex::{ Regex, RegexBuilder };
+use serde::{ Deserialize, Serialize };
+
+use crate::log_record::{ LogLevel, LogRecord };
+
+const DEFAULT_PAGE_SIZE: usize = 200;
+const MAX_PAGE_SIZE: usize = 2000;
+
+/// Filters for `query_logs`, every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogQuery {
+  /// Only records of these levels, any level if empty
+  pub levels: Vec<LogLevel>,
+  /// Inclusive bounds as `HH:MM:SS`
+  pub from: Option<String>,
+  pub to: Option<String>,
+  /// Case insensitive substring of the logger or thread name, usually a mod
+  pub logger: Option<String>,
+  /// Case insensitive regex matched against the whole record
+  pub pattern: Option<String>,
+  pub offset: usize,
+  pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogPage {
+  pub records: Vec<LogRecord>,
+  /// Records matching the query, across all pages
+  pub total: usize,
+  pub offset: usize,
+}
+
+struct CompiledQuery<'a> {
+  query: &'a LogQuery,
+  logger: Option<String>,
+  pattern: Option<Regex>,
+}
+
+impl CompiledQuery<'_> {
+  fn matches(&self, record: &LogRecord) -> bool {
+    let query = self.query;
+    if !query.levels.is_empty() && !query.levels.contains(&record.level) {
+      return false;
+    }
+    // Records without a time (like lines before log4j starts) only match when no range is given
+    if query.from.is_some() || query.to.is_some() {
+      let Some(time) = record.time.as_deref().map(|time| &time[..time.len().min(8)]) else {
+        return false;
+      };
+      if query.from.as_deref().is_some_and(|from| time < from) || query.to.as_deref().is_some_and(|to| time > to) {
+        return false;
+      }
+    }
+    if let Some(logger) = &self.logger {
+      let names = [&record.logger, &record.thread];
+      if !names.iter().any(|name| name.as_ref().is_some_and(|name| name.to_lowercase().contains(logger))) {
+        return false;
+      }
+    }
+    self.pattern.as_ref().is_none_or(|pattern| pattern.is_match(&record.raw))
+  }
+}
+
+impl LogQuery {
+  pub fn run<'a>(&self, records: impl IntoIterator<Item = &'a LogRecord>) -> Result<LogPage, regex::Error> {
+    let compiled = CompiledQuery {
+      query: self,
+      logger: self.logger.as_ref().map(|logger| logger.to_lowercase()).filter(|logger| !logger.is_empty()),
+      pattern: self.pattern
+        .as_ref()
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+        .transpose()?,
+    };
+    let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+
+    let mut total = 0;
+    let mut page = vec![];
+    for record in records.into_iter().filter(|record| compiled.matches(record)) {
+      if total >= self.offset && page.len() < limit {
+        page.push(record.clone());
+      }
+      total += 1;
+    }
+    Ok(LogPage { records: page, total, offset: self.offset })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::log_record::{ parse_records, LogFormat };
+
+  use super::*;
+
+  const LOG: &str = "\
+[12:00:00] [main/INFO] [cpw.mods.modlauncher.Launcher/MODLAUNCHER]: ModLauncher running
+[12:00:05] [Worker-Main-1/WARN] [com.simibubi.create.Create/]: Create is loading
+[12:00:10] [Render thread/ERROR]: Exception caught
+java.lang.NullPointerException: null
+\tat net.minecraft.client.Minecraft.run(Minecraft.java:1)
+[12:01:00] [Render thread/INFO]: Stopping!";
+
+  fn run(query: LogQuery) -> LogPage {
+    query.run(&parse_records(LogFormat::Game, LOG)).unwrap()
+  }
+
+  #[test]
+  fn filters_by_level_and_time() {
+    let page = run(LogQuery { levels: vec![LogLevel::Warn, LogLevel::Error], ..Default::default() });
+    assert_eq!(page.total, 2);
+
+    let page = run(LogQuery { from: Some("12:00:05".into()), to: Some("12:00:10".into()), ..Default::default() });
+    assert_eq!(page.total, 2);
+    assert_eq!(page.records[1].message.lines().count(), 3);
+  }
+
+  #[test]
+  fn filters_by_logger_and_pattern() {
+    assert_eq!(run(LogQuery { logger: Some("create".into()), ..Default::default() }).total, 1);
+    assert_eq!(run(LogQuery { logger: Some("render".into()), ..Default::default() }).total, 2);
+    assert_eq!(run(LogQuery { pattern: Some("nullpointer".into()), ..Default::default() }).total, 1);
+    assert!(LogQuery { pattern: Some("(".into()), ..Default::default() }.run(&[]).is_err());
+  }
+
+  #[test]
+  fn pages_results() {
+    let page = run(LogQuery { offset: 1, limit: Some(2), ..Default::default() });
+    assert_eq!(page.total, 4);
+    assert_eq!(page.records.len(), 2);
+    assert_eq!(page.records[0].time.as_deref(), Some("12:00:05"));
+  }
+}
@@ -0,0 +1,218 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{ Deserialize, Serialize };
+
+/// Lines appended to a single record before starting a new one, so unformatted output can't grow forever
+const MAX_RECORD_LINES: usize = 500;
+
+/// `[12:00:00] [Render thread/INFO] [net.minecraft.client.Minecraft/]: Message`, the logger is only printed by forge
+static GAME_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(
+    r"^\[(?P<time>\d{2}:\d{2}:\d{2}(?:\.\d+)?)\] \[(?P<thread>.+?)/(?P<level>TRACE|DEBUG|INFO|WARN|ERROR|FATAL)\](?: \[(?P<logger>[^/\]]*)(?:/[^\]]*)?\])?: (?P<message>.*)$"
+  ).unwrap()
+});
+/// `[12:00:00 INFO]: Message`, as written by the launcher appender
+static LAUNCHER_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^\[(?P<time>\d{2}:\d{2}:\d{2}) (?P<level>TRACE|DEBUG|INFO|WARN|ERROR)\]: (?P<message>.*)$").unwrap()
+});
+
+/// Lines of a java stack trace: frames, causes, omitted frames and the exception header
+static STACK_TRACE_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(
+    r#"^(?:\s+at |\s*(?:Caused by|Suppressed): |\s*\.\.\. \d+ (?:more|common frames omitted)$|(?:Exception in thread "[^"]*" )?(?:[\w$]+\.)+[\w$]*(?:Exception|Error|Throwable)(?:: .*)?$)"#
+  ).unwrap()
+});
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+  Trace,
+  Debug,
+  #[default]
+  Info,
+  Warn,
+  Error,
+  Fatal,
+}
+
+impl LogLevel {
+  fn parse(level: &str) -> Self {
+    match level {
+      "TRACE" => Self::Trace,
+      "DEBUG" => Self::Debug,
+      "WARN" => Self::Warn,
+      "ERROR" => Self::Error,
+      "FATAL" => Self::Fatal,
+      _ => Self::Info,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+  /// log4j console output of minecraft and forge
+  Game,
+  Launcher,
+}
+
+impl LogFormat {
+  fn regex(&self) -> &'static Regex {
+    match self {
+      Self::Game => &GAME_LINE_RE,
+      Self::Launcher => &LAUNCHER_LINE_RE,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogRecord {
+  /// Increasing id, a record that grows with continuation lines is emitted again with the same id
+  pub id: u64,
+  pub time: Option<String>,
+  pub thread: Option<String>,
+  pub level: LogLevel,
+  pub logger: Option<String>,
+  /// Message of the first line followed by its continuation lines, like stack traces
+  pub message: String,
+  /// Original text of every line in the record
+  pub raw: String,
+  #[serde(skip)]
+  lines: usize,
+}
+
+impl LogRecord {
+  /// Parses a line that starts a new record, or `None` if it continues the previous one
+  pub fn parse(id: u64, format: LogFormat, line: &str) -> Option<Self> {
+    let captures = format.regex().captures(line)?;
+    let get = |name: &str| captures.name(name).map(|m| m.as_str().to_string()).filter(|s| !s.is_empty());
+    Some(Self {
+      id,
+      time: get("time"),
+      thread: get("thread"),
+      level: captures.name("level").map(|m| LogLevel::parse(m.as_str())).unwrap_or_default(),
+      logger: get("logger"),
+      message: get("message").unwrap_or_default(),
+      raw: line.to_string(),
+      lines: 1,
+    })
+  }
+
+  /// A record for a line without header, inheriting the level of the previous record
+  pub fn unformatted(id: u64, level: LogLevel, line: &str) -> Self {
+    Self {
+      id,
+      time: None,
+      thread: None,
+      level,
+      logger: None,
+      message: line.to_string(),
+      raw: line.to_string(),
+      lines: 1,
+    }
+  }
+
+  /// Whether a line without header belongs to the previous record. Only stack traces do, any other output
+  /// (like stderr interleaved with stdout) starts a record of its own.
+  pub fn is_continuation(line: &str) -> bool {
+    STACK_TRACE_LINE_RE.is_match(line)
+  }
+
+  /// Appends a continuation line, returns `false` if the record is already full
+  pub fn append(&mut self, line: &str) -> bool {
+    if self.lines >= MAX_RECORD_LINES {
+      return false;
+    }
+    self.message.push('\n');
+    self.message.push_str(line);
+    self.raw.push('\n');
+    self.raw.push_str(line);
+    self.lines += 1;
+    true
+  }
+}
+
+/// Groups the lines of a whole log into records, like `LogFlusher` does while the game runs
+pub fn parse_records(format: LogFormat, text: &str) -> Vec<LogRecord> {
+  let mut records: Vec<LogRecord> = vec![];
+  for line in text.lines() {
+    let id = records.len() as u64;
+    match LogRecord::parse(id, format, line) {
+      Some(record) => records.push(record),
+      None => {
+        if let Some(last) = records.last_mut().filter(|_| LogRecord::is_continuation(line)) {
+          if last.append(line) {
+            continue;
+          }
+        }
+        let level = records.last().map(|last| last.level).unwrap_or_default();
+        records.push(LogRecord::unformatted(id, level, line));
+      }
+    }
+  }
+  records
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_vanilla_and_forge_lines() {
+    let vanilla = LogRecord::parse(0, LogFormat::Game, "[12:00:01] [Render thread/WARN]: Missing sound for event").unwrap();
+    assert_eq!(vanilla.time.as_deref(), Some("12:00:01"));
+    assert_eq!(vanilla.thread.as_deref(), Some("Render thread"));
+    assert_eq!(vanilla.level, LogLevel::Warn);
+    assert_eq!(vanilla.logger, None);
+    assert_eq!(vanilla.message, "Missing sound for event");
+
+    let forge = LogRecord::parse(
+      1,
+      LogFormat::Game,
+      "[12:00:02] [main/INFO] [cpw.mods.modlauncher.Launcher/MODLAUNCHER]: ModLauncher running: args []"
+    ).unwrap();
+    assert_eq!(forge.thread.as_deref(), Some("main"));
+    assert_eq!(forge.logger.as_deref(), Some("cpw.mods.modlauncher.Launcher"));
+    assert_eq!(forge.message, "ModLauncher running: args []");
+  }
+
+  #[test]
+  fn parses_launcher_lines() {
+    let record = LogRecord::parse(0, LogFormat::Launcher, "[12:00:01 ERROR]: Failed to start game").unwrap();
+    assert_eq!(record.level, LogLevel::Error);
+    assert_eq!(record.message, "Failed to start game");
+    assert!(LogRecord::parse(0, LogFormat::Game, "[12:00:01 ERROR]: Failed to start game").is_none());
+  }
+
+  #[test]
+  fn groups_stack_traces() {
+    let mut record = LogRecord::parse(0, LogFormat::Game, "[12:00:01] [main/ERROR]: Exception caught").unwrap();
+    assert!(LogRecord::parse(1, LogFormat::Game, "java.lang.NullPointerException: null").is_none());
+    assert!(record.append("java.lang.NullPointerException: null"));
+    assert!(record.append("\tat net.minecraft.client.Minecraft.run(Minecraft.java:1)"));
+    assert_eq!(record.message.lines().count(), 3);
+    assert!(record.raw.starts_with("[12:00:01] [main/ERROR]: Exception caught\n"));
+  }
+
+  #[test]
+  fn only_stack_traces_continue_records() {
+    for line in [
+      "java.lang.IllegalStateException: Broken mod",
+      "Exception in thread \"main\" java.lang.NoClassDefFoundError: net/minecraft/Foo",
+      "\tat net.minecraft.client.Minecraft.run(Minecraft.java:1)",
+      "Caused by: java.io.IOException: Stream closed",
+      "\tSuppressed: java.lang.RuntimeException",
+      "\t... 12 more",
+    ] {
+      assert!(LogRecord::is_continuation(line), "{line}");
+    }
+    for line in ["OpenAL initialized.", "Error: could not open file", "Exception caught"] {
+      assert!(!LogRecord::is_continuation(line), "{line}");
+    }
+
+    let text = "[12:00:01] [main/ERROR]: Exception caught\njava.lang.NullPointerException\n\tat a.b.C.run(C.java:1)\nOpenAL initialized.";
+    let records = parse_records(LogFormat::Game, text);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].message.lines().count(), 3);
+    assert_eq!(records[1].message, "OpenAL initialized.");
+  }
+}
@@ -1,4 +1,4 @@
-use std::{ fs::{ self, File }, io::Write, path::Path, sync::Mutex };
+use std::{ fs::{ self, File }, io::Write, path::{ Path, PathBuf }, sync::Mutex };
 
 use chrono::Utc;
 use flate2::{ write::GzEncoder, Compression };
@@ -9,7 +9,11 @@ use log4rs::{
   encode::{ pattern::PatternEncoder, writer::simple::SimpleWriter, Encode },
   filter::{ Filter, Response },
   Config,
+  Handle,
 };
+use once_cell::sync::OnceCell;
+
+use crate::{ config::logging::LoggingConfig, log_flusher::{ set_all_capacities, LOG_FLUSHER_TARGET } };
 
 #[derive(Debug)]
 struct LogLevelFilter(LevelFilter);
@@ -34,6 +38,22 @@ impl Filter for ModuleFilter {
   }
 }
 
+#[derive(Debug)]
+struct TargetFilter(&'static str);
+
+impl Filter for TargetFilter {
+  fn filter(&self, record: &Record) -> Response {
+    if record.target() == self.0 { Response::Reject } else { Response::Neutral }
+  }
+}
+
+struct LoggerHandle {
+  handle: Handle,
+  latest_log: PathBuf,
+}
+
+static LOGGER: OnceCell<LoggerHandle> = OnceCell::new();
+
 type Callback = Box<dyn (Fn(&str) -> Result<(), Box<dyn std::error::Error>>) + Send + Sync>;
 static CALLBACKS: Mutex<Vec<Callback>> = Mutex::new(vec![]);
 
@@ -97,27 +117,43 @@ pub fn setup_logger(logs_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
     fs::remove_file(&latest_log)?;
   }
 
+  let config = build_config(&latest_log, &LoggingConfig::default())?;
+  let handle = log4rs::init_config(config)?;
+  let _ = LOGGER.set(LoggerHandle { handle, latest_log });
+
+  Ok(())
+}
+
+/// Applies the levels of the config to the running logger and resizes the log buffers
+pub fn apply_logging_config(logging: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
+  set_all_capacities(logging.buffer_size());
+  let logger = LOGGER.get().ok_or("Logger is not initialized")?;
+  logger.handle.set_config(build_config(&logger.latest_log, logging)?);
+  Ok(())
+}
+
+fn build_config(latest_log: &Path, logging: &LoggingConfig) -> Result<Config, Box<dyn std::error::Error>> {
   let console_encoder = PatternEncoder::new("[{d(%H:%M:%S)}] [{M}/{h({l})}]: {m}{n}");
   let launcher_encoder = PatternEncoder::new("[{d(%H:%M:%S)} {l}]: {m}{n}");
   let file_encoder = PatternEncoder::new("{d(%Y-%m-%d %H:%M:%S)} | {({l}):5.5} | {f}:{L} — {m}{n}");
 
-  let stdout_appender = Appender::builder().build("stdout", Box::new(ConsoleAppender::builder().encoder(Box::new(console_encoder)).build()));
+  let stdout_appender = Appender::builder()
+    .filter(Box::new(LogLevelFilter(LevelFilter::Debug)))
+    .build("stdout", Box::new(ConsoleAppender::builder().encoder(Box::new(console_encoder)).build()));
   let launcher_appender = Appender::builder()
     .filter(Box::new(ModuleFilter("tao".to_string())))
-    .filter(Box::new(LogLevelFilter(LevelFilter::Info)))
+    .filter(Box::new(TargetFilter(LOG_FLUSHER_TARGET)))
+    .filter(Box::new(LogLevelFilter(logging.ui_level)))
     .build("launcher", Box::new(LauncherAppender::new(Box::new(launcher_encoder))));
 
+  // Appends, so reconfiguring doesn't truncate the current log
   let file_appender = FileAppender::builder().encoder(Box::new(file_encoder)).build(latest_log)?;
+  let file_appender = Appender::builder()
+    .filter(Box::new(LogLevelFilter(logging.file_level)))
+    .build("log_file", Box::new(file_appender));
 
-  let root = Root::builder().appender("stdout").appender("launcher").appender("log_file").build(LevelFilter::Debug);
+  let root_level = LevelFilter::Debug.max(logging.ui_level).max(logging.file_level);
+  let root = Root::builder().appender("stdout").appender("launcher").appender("log_file").build(root_level);
 
-  let config = Config::builder()
-    .appender(stdout_appender)
-    .appender(launcher_appender)
-    .appender(Appender::builder().build("log_file", Box::new(file_appender)))
-    .build(root)
-    .expect("Failed to create log4rs config");
-  log4rs::init_config(config)?;
-
-  Ok(())
+  Ok(Config::builder().appender(stdout_appender).appender(launcher_appender).appender(file_appender).build(root)?)
 }
@@ -14,9 +14,10 @@ use rsa::{ sha2::Sha256, Pkcs1v15Sign };
 use serde::{ Deserialize, Serialize };
 use sha1::Digest;
 use tokio::{ fs::File, io::{ AsyncWriteExt, self } };
+use tokio_util::sync::CancellationToken;
 use zip::ZipArchive;
 
-use crate::modpack_downloader::keys::{ get_aes_keys, get_public_key };
+use crate::{ app::error::LauncherError, modpack_downloader::keys::{ get_aes_keys, get_public_key } };
 
 /*
 Process:
@@ -76,17 +77,40 @@ impl ModpackProvider {
     Ok(modpack_info)
   }
 
-  pub async fn reconstruct_encrypted_modpack(&self, monitor: ProgressReporter) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+  pub async fn reconstruct_encrypted_modpack(
+    &self,
+    monitor: ProgressReporter,
+    cancel_token: &CancellationToken
+  ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let ModpackInfo { parts, .. } = self.fetch_info().await?;
     assert!(!parts.is_empty(), "No modpack parts provided");
     let tmp_dir = std::env::temp_dir().join(format!("modpack-{}", Utc::now().timestamp_millis()));
     create_dir_all(&tmp_dir)?;
 
-    async fn download_part(client: &Client, url: &Url, target: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let result = self.download_parts(&parts, &tmp_dir, &monitor, cancel_token).await;
+    let _ = fs::remove_dir_all(&tmp_dir);
+    result
+  }
+
+  async fn download_parts(
+    &self,
+    parts: &[String],
+    tmp_dir: &Path,
+    monitor: &ProgressReporter,
+    cancel_token: &CancellationToken
+  ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    async fn download_part(client: &Client, url: &Url, target: &PathBuf, cancel_token: &CancellationToken) -> Result<(), Box<dyn std::error::Error>> {
       let response = client.get(url.as_str()).send().await?.error_for_status()?;
       let mut stream = response.bytes_stream();
       let mut file = File::create(target).await?;
-      while let Some(Ok(chunk)) = stream.next().await {
+      loop {
+        let chunk = tokio::select! {
+          chunk = stream.next() => chunk,
+          _ = cancel_token.cancelled() => return Err(LauncherError::Cancelled.into()),
+        };
+        let Some(Ok(chunk)) = chunk else {
+          break;
+        };
         file.write_all(&chunk).await?;
         file.flush().await?;
       }
@@ -96,13 +120,16 @@ impl ModpackProvider {
     // TODO: concurrency!!!
     monitor.setup("Downloading modpack parts", Some(parts.len()));
     let mut progress = 0;
-    for file_name in &parts {
+    for file_name in parts {
       let mut attempts = 0;
       let url = self.base_url.join(file_name)?;
       let target = tmp_dir.join(file_name);
       while attempts < 5 {
+        if cancel_token.is_cancelled() {
+          return Err(LauncherError::Cancelled.into());
+        }
         info!("Downloading {} (attempt {})", file_name, attempts + 1);
-        let result = download_part(&self.client, &url, &target).await;
+        let result = download_part(&self.client, &url, &target, cancel_token).await;
         if let Err(e) = result {
           error!("Error downloading {}: {}", file_name, e);
           attempts += 1;
@@ -156,7 +183,12 @@ impl ModpackDownloader {
     Ok(self.modpack_info.as_ref().unwrap())
   }
 
-  pub async fn download_and_install(&mut self, monitor: ProgressReporter, chosen_optionals: Vec<String>) -> Result<(), StdError> {
+  pub async fn download_and_install(
+    &mut self,
+    monitor: ProgressReporter,
+    chosen_optionals: Vec<String>,
+    cancel_token: &CancellationToken
+  ) -> Result<(), StdError> {
     let (public_key, aes_keys) = (get_public_key()?, get_aes_keys()?);
 
     let local_modpack_dir_path = &self.mc_dir.join("modpack");
@@ -185,6 +217,10 @@ impl ModpackDownloader {
     let total_providers = self.providers.len();
     monitor.setup("Trying modpack providers", Some(total_providers));
     for (i, provider) in self.providers.iter().enumerate() {
+      if cancel_token.is_cancelled() {
+        monitor.done();
+        return Err(LauncherError::Cancelled.into());
+      }
       monitor.status(&format!("Trying modpack provider {} ({}/{})", provider.base_url.as_str(), i + 1, total_providers));
       monitor.progress(i);
       info!(" - Trying provider '{}'", provider.base_url);
@@ -217,7 +253,7 @@ impl ModpackDownloader {
 
       // Download it
       info!("   Downloading modpack...");
-      let remote_modpack = provider.reconstruct_encrypted_modpack(monitor.clone()).await?;
+      let remote_modpack = provider.reconstruct_encrypted_modpack(monitor.clone(), cancel_token).await?;
       monitor.done();
 
       // Verify installation
@@ -259,6 +295,10 @@ impl ModpackDownloader {
       return Ok(());
     }
 
+    if cancel_token.is_cancelled() {
+      return Err(LauncherError::Cancelled.into());
+    }
+
     let aes_decoder = Aes256CbcDec::new_from_slices(aes_keys.key(), aes_keys.iv())?;
     monitor.setup("Installing modpack", Some(1));
     if let Err(err) = self.try_install_modpack(aes_decoder, &local_modpack_path, chosen_optionals) {
@@ -1,9 +1,65 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { writable } from "svelte/store";
 
-export const loginCracked = (username: string) => {
-  return invoke("login_offline", { username });
+export type DeviceCode = {
+  user_code: string;
+  verification_uri: string;
+  expires_in: number;
+};
+
+/** Set while the login page is open to add another account */
+export const addingAccountStore = writable(false);
+
+/** Uuid of an offline player: the one offline servers give it, or a fixed one */
+export type OfflineUuid = { scheme: "mojang" } | { scheme: "fixed"; uuid: string };
+
+export const loginCracked = (username: string, uuid?: OfflineUuid) => {
+  return invoke("login_offline", { username, uuid });
+};
+
+export const isOfflineLoginAllowed = () => {
+  return invoke<boolean>("is_offline_login_allowed");
 };
 
 export const loginMicrosoft = () => {
   return invoke("login_msa");
 };
+
+/**
+ * Logs in with a code entered in the browser, without opening the login window
+ * @param onCode Receives the code the user has to enter
+ */
+export const loginMicrosoftDevice = async (onCode: (code: DeviceCode) => void) => {
+  const unlisten = await listen<DeviceCode>("msa_device_code", (event) => onCode(event.payload));
+  try {
+    await invoke("login_msa_device");
+  } finally {
+    unlisten();
+  }
+};
+
+export const cancelMicrosoftDeviceLogin = () => {
+  return invoke("cancel_msa_device_login");
+};
+
+export const switchAccount = (id: string) => {
+  return invoke("switch_account", { id });
+};
+
+export const renameAccount = (id: string, label?: string) => {
+  return invoke("rename_account", { id, label });
+};
+
+export const removeAccount = (id: string) => {
+  return invoke("remove_account", { id });
+};
+
+export const refreshAccount = (id: string) => {
+  return invoke("refresh_account", { id });
+};
+
+/** Removes the active account with its stored tokens and forgets the Microsoft session of the login window */
+export const logout = () => {
+  return invoke("logout");
+};
@@ -0,0 +1,18 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type Diagnosis = {
+  id: string;
+  title: string;
+  description: string;
+  fix: string;
+  source: "game_logs" | "crash_report";
+  matched_line: string;
+};
+
+export const analyzeGameLogs = () => {
+  return invoke<Diagnosis[]>("analyze_game_logs");
+};
+
+export const exportSupportBundle = () => {
+  return invoke<string>("export_support_bundle");
+};
@@ -0,0 +1,19 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type LaunchPreview = {
+  java_path: string;
+  directory: string;
+  jvm_args: string[];
+  main_class?: string;
+  game_args: string[];
+};
+
+export type ScriptFormat = "shell" | "batch";
+
+export const dryRunLaunch = () => {
+  return invoke<LaunchPreview>("dry_run_launch");
+};
+
+export const exportLaunchScript = (preview: LaunchPreview, format?: ScriptFormat) => {
+  return invoke<string>("export_launch_script", { preview, format });
+};
@@ -0,0 +1,25 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { Log, LogLevel } from "./stores/loggers";
+
+export type LogQuery = {
+  levels?: LogLevel[];
+  from?: string;
+  to?: string;
+  logger?: string;
+  pattern?: string;
+  offset?: number;
+  limit?: number;
+};
+
+export type LogPage = {
+  records: Log[];
+  total: number;
+  offset: number;
+};
+
+/**
+ * @param source `game_logs`, `launcher_logs` or the name of a previous game session
+ */
+export const queryLogs = (source: string, query: LogQuery) => {
+  return invoke<LogPage>("query_logs", { source, query });
+};
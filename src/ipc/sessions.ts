@@ -0,0 +1,16 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type GameSession = {
+  name: string;
+  date: string;
+  size: number;
+  active: boolean;
+};
+
+export const listGameSessions = () => {
+  return invoke<GameSession[]>("list_game_sessions");
+};
+
+export const readGameSession = (name: string) => {
+  return invoke<string>("read_game_session", { name });
+};
@@ -0,0 +1,44 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type SkinVariant = "CLASSIC" | "SLIM";
+export type TextureState = "ACTIVE" | "INACTIVE";
+
+export type Skin = {
+  id: string;
+  state: TextureState;
+  textureKey: string;
+  url: string;
+  variant?: SkinVariant;
+};
+
+export type Cape = {
+  id: string;
+  state: TextureState;
+  alias: string;
+  url: string;
+};
+
+export type PlayerProfile = {
+  id: string;
+  name: string;
+  skins?: Skin[];
+  capes?: Cape[];
+};
+
+export const getPlayerProfile = () => {
+  return invoke<PlayerProfile>("get_player_profile");
+};
+
+export const uploadSkin = async (file: File, variant: SkinVariant) => {
+  const png = Array.from(new Uint8Array(await file.arrayBuffer()));
+  return invoke<PlayerProfile>("upload_skin", { png, variant });
+};
+
+export const resetSkin = () => {
+  return invoke<PlayerProfile>("reset_skin");
+};
+
+/** @param capeId Cape to show, or `undefined` to hide the active one */
+export const setCape = (capeId?: string) => {
+  return invoke<PlayerProfile>("set_cape", { capeId });
+};
@@ -0,0 +1,33 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { writable } from "svelte/store";
+
+export type CrashSummary = {
+  exit_code: number;
+  kind: "Game" | "Jvm" | "Unknown";
+  description?: string;
+  time?: string;
+  suspected_mods: string[];
+  excerpt: string[];
+  report_path?: string;
+  other_reports: string[];
+};
+
+function createCrashStore() {
+  const { subscribe, set } = writable<CrashSummary | undefined>(undefined, (set) => {
+    invoke("get_last_crash").then((crash) => set((crash as CrashSummary | null) ?? undefined));
+
+    const unsubscriber = listen("game_crashed", ({ payload }) => {
+      set(payload as CrashSummary);
+    });
+    return () => unsubscriber.then((unlisten) => unlisten());
+  });
+
+  const openReport = () => invoke("open_crash_report");
+  const exportReport = () => invoke<string>("export_crash_report");
+  const dismiss = () => set(undefined);
+
+  return { subscribe, openReport, exportReport, dismiss };
+}
+
+export const crashStore = createCrashStore();
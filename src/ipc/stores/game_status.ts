@@ -28,7 +28,17 @@ function createGameStatusStore() {
     await invoke("start_game");
   }
 
-  return { subscribe: store.subscribe, startGame };
+  async function cancelLaunch() {
+    if (get(store) !== GameStatus.Downloading) return;
+    await invoke("cancel_launch");
+  }
+
+  async function killGame() {
+    if (get(store) !== GameStatus.Playing) return;
+    await invoke("kill_game");
+  }
+
+  return { subscribe: store.subscribe, startGame, cancelLaunch, killGame };
 }
 
 export const gameStatusStore = createGameStatusStore();
@@ -6,19 +6,35 @@ const appWindow = getCurrentWebviewWindow()
 export type MsaAuthentication = {
   username: string;
   uuid: string;
-  moj_token: string;
   moj_expiration_date: number;
-  msa_access_token: string;
-  msa_refresh_token: string;
-  msa_expiration_date: string;
+  msa_expiration_date: number;
 };
 export type OfflineAuthentication = { username: string; uuid: string };
+export type Authentication = OfflineAuthentication | MsaAuthentication;
+
+export type Account = {
+  id: string;
+  label?: string;
+  authentication: Authentication;
+};
+
+export type LevelFilter = "OFF" | "ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE";
+export type LoggingConfig = {
+  buffer_size: number;
+  ui_level: LevelFilter;
+  file_level: LevelFilter;
+};
 
 export type LauncherConfig = {
-  authentication?: OfflineAuthentication | MsaAuthentication;
+  accounts?: Account[];
+  active_account?: string;
+  memory_min: number;
   memory_max: number;
   selected_options?: string[];
   jre_flags?: string;
+  logging?: LoggingConfig;
+  msa_login_timeout?: number;
+  clock_skew_margin?: number;
 };
 
 /**
@@ -26,7 +42,7 @@ export type LauncherConfig = {
  * @returns A svelte store with the launcher config
  */
 function createLauncherConfigStore() {
-  let store = writable<LauncherConfig>({ memory_max: 1024 }, (set) => {
+  let store = writable<LauncherConfig>({ memory_min: 512, memory_max: 1024 }, (set) => {
     // Ask for the config
     invoke("get_launcher_config").then((config) => set(config as LauncherConfig));
 
@@ -49,19 +65,15 @@ function createLauncherConfigStore() {
     set(updater(config));
   }
 
-  function logout() {
-    update((config) => {
-      config.authentication = undefined;
-      return config;
-    });
-  }
-
   return {
     subscribe: store.subscribe,
     set,
     update,
-    logout,
   };
 }
 
+export const getActiveAccount = (config: LauncherConfig) => {
+  return config.accounts?.find((account) => account.id === config.active_account);
+};
+
 export const launcherConfigStore = createLauncherConfigStore();
@@ -1,54 +1,96 @@
-import { writable } from "svelte/store";
+import { get, writable } from "svelte/store";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
+import { launcherConfigStore } from "./launcher_config";
 
-export type Log = { level: string; message: string };
+export type LogLevel = "trace" | "debug" | "info" | "warn" | "error" | "fatal";
+
+export type Log = {
+  id: number;
+  time?: string;
+  thread?: string;
+  level: LogLevel;
+  logger?: string;
+  message: string;
+  raw: string;
+};
+
+// Same capacity as the backend buffers, which clamp the configured size to this range
+const MIN_LOGS = 100;
+const MAX_LOGS = 50000;
+let maxLogs = 1000;
+launcherConfigStore.subscribe((config) => {
+  const size = Number(config.logging?.buffer_size);
+  if (Number.isFinite(size)) {
+    maxLogs = Math.min(Math.max(size, MIN_LOGS), MAX_LOGS);
+  }
+});
+
+// Logs added by the frontend can land after the record that is still growing
+const RECENT_WINDOW = 8;
+
+function findRecent(arr: Log[], id: number) {
+  for (let i = arr.length - 1; i >= Math.max(0, arr.length - RECENT_WINDOW); i--) {
+    if (arr[i].id === id) {
+      return i;
+    }
+  }
+  return -1;
+}
 
 /**
  *
- * @param listenerName Name of the tauri event to listen to
- * @param commandName Name of the tauri command to invoke the first time
+ * @param id Name of the tauri event to listen to, also used to get the cached logs
  * @returns A svelte store with the logs
  */
-function createLogsStore(id: string, pattern: RegExp) {
+function createLogsStore(id: string) {
+  // Ids for logs created by the frontend, negative so they never collide with the backend ones
+  let localId = -1;
+
   let { subscribe, set, update } = writable<Log[]>([], (set) => {
     // Get logs from cache
-    invoke("plugin:log-flusher|get_logs", { id }).then((logs) => {
-      log(...(logs as string[]));
+    invoke<Log[]>("plugin:log-flusher|get_logs", { id }).then((logs) => {
+      // Events may have arrived first, the cache is only checked against the whole buffer once
+      const known = new Set(get({ subscribe }).map((log) => log.id));
+      push(...logs.filter((log) => !known.has(log.id)));
     });
 
     // Listen for logs
-    let unsubscriber = listen(id, (event) => {
-      let logs = event.payload as string | string[];
-      log(...logs);
+    let unsubscriber = listen<Log[]>(id, (event) => {
+      push(...event.payload);
     });
 
     // Register unsubscriber
     return () => unsubscriber.then((unlisten) => unlisten());
   });
 
-  function toLog(lastLevel: string | undefined, message: string) {
-    const matches = pattern.exec(message) ?? [];
-    const level = matches.length > 1 ? matches[1].toLowerCase() : lastLevel ?? "info";
-    return { level, message } as Log;
-  }
-
-  function log(...logs: string[]) {
+  // Records that grow with continuation lines (like stack traces) are sent again with the same id.
+  // Only the latest record can grow, so just the end of the buffer is searched
+  function push(...logs: Log[]) {
     update((arr) => {
       for (const log of logs) {
-        arr.push(toLog(arr[arr.length - 1]?.level, log));
+        const index = findRecent(arr, log.id);
+        if (index >= 0) {
+          arr[index] = log;
+        } else {
+          arr.push(log);
+        }
       }
-      while (arr.length >= 1001) {
-        arr.shift();
+      if (arr.length > maxLogs) {
+        arr.splice(0, arr.length - maxLogs);
       }
       return arr;
     });
   }
 
+  function log(...messages: string[]) {
+    push(...messages.map((message) => ({ id: localId--, level: "error", message, raw: message }) as Log));
+  }
+
   const clear = () => set([]);
 
   return { subscribe, log, clear };
 }
 
-export const gameLogsStore = createLogsStore("game_logs", /^\[(?:[0-9:]+)\] \[[^/]+\/([^\]]+)\]/m);
-export const launcherLogsStore = createLogsStore("launcher_logs", /^\[(?:[0-9:]+) ([^\]]+)\]:/m);
+export const gameLogsStore = createLogsStore("game_logs");
+export const launcherLogsStore = createLogsStore("launcher_logs");
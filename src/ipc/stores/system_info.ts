@@ -5,6 +5,18 @@ export const totalMemoryStore = readable(0, (set) => {
   invoke("get_system_memory").then((memory) => set(memory as number));
 });
 
+export type MemoryInfo = {
+  total_mb: number;
+  available_mb: number;
+  jvm_64bit: boolean;
+  max_allocatable_mb: number;
+  recommended_mb: number;
+};
+
+export const memoryInfoStore = readable<MemoryInfo | undefined>(undefined, (set) => {
+  invoke("get_memory_info").then((info) => set(info as MemoryInfo));
+});
+
 export const defaultJREFlags = readable({} as { [key: string]: string }, (set) => {
   invoke("get_default_jre_flags").then((flags) => set(flags as { [key: string]: string }));
 });